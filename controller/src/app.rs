@@ -1,10 +1,18 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 use crate::color::Palette;
+use crate::gradient::GradientEditor;
+use crate::schedule::Schedule;
+use js_sys::Uint8Array;
 use shared::{
-    Color, ControlMessage, PaletteStateChange, StateChange, SubscriberConfig, SubscriberId,
-    SubscriberStateChange,
+    BeatEffect, BeatPattern, ClientCounts, ClockControlMessage, ClockStateChange, Color,
+    ControlMessage, CueControlMessage, CueStateChange, EffectControlMessage, EffectId,
+    EffectStateChange, Extraction, Gradient, ImageUpload, MasterControlMessage, MasterStateChange,
+    PaletteControlMessage, PaletteFile, PaletteFormat, PaletteStateChange, ScheduleId,
+    ScheduleStateChange, Scheduled, SortBy, StateChange, SubscriberId, SubscriberStateChange,
 };
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{Blob, HtmlAnchorElement, HtmlInputElement, HtmlSelectElement, Url};
 use yew::prelude::*;
 use yew_agent::{Bridge, Bridged};
 
@@ -14,11 +22,39 @@ use crate::websocket::WebsocketService;
 pub enum Msg {
     HandleStateChange(StateChange),
     Send(ControlMessage),
+    /// Upload an image to set the palette from its dominant colors.
+    Upload(ImageUpload),
+    /// An image or palette file could not be read.
+    UploadFailed(String),
 }
 
 pub struct App {
     palette: Vec<Color>,
-    subscribers: HashMap<SubscriberId, SubscriberConfig>,
+    /// The gradient the palette was sampled from, if any.
+    gradient: Option<Gradient>,
+    subscribers: HashMap<SubscriberId, shared::Subscriber>,
+    level: f32,
+    blackout: bool,
+    frozen: bool,
+    swatch_locks: Vec<bool>,
+    /// Whether the palette rejects edits.
+    locked: bool,
+    cues: Vec<shared::Cue>,
+    current_cue: Option<usize>,
+    cues_paused: bool,
+    bpm: f32,
+    beat_effect: Option<BeatEffect>,
+    /// Running continuous effects, in the order they are applied.
+    effects: Vec<(EffectId, shared::Effect)>,
+    saved_palettes: BTreeMap<String, Vec<Color>>,
+    schedule: Vec<(ScheduleId, Scheduled)>,
+    clients: ClientCounts,
+    /// The most recent error reported by the server.
+    error: Option<String>,
+    save_name: NodeRef,
+    export_format: NodeRef,
+    /// A hidden link used to download exported palettes.
+    download_link: NodeRef,
     _producer: Box<dyn Bridge<EventBus>>,
     wss: WebsocketService,
 }
@@ -33,7 +69,26 @@ impl Component for App {
 
         Self {
             palette: vec![],
+            gradient: None,
             subscribers: HashMap::new(),
+            level: 1.0,
+            blackout: false,
+            frozen: false,
+            swatch_locks: vec![],
+            locked: false,
+            cues: vec![],
+            current_cue: None,
+            cues_paused: false,
+            bpm: 120.,
+            beat_effect: None,
+            effects: vec![],
+            saved_palettes: BTreeMap::new(),
+            schedule: vec![],
+            clients: ClientCounts::default(),
+            error: None,
+            save_name: NodeRef::default(),
+            export_format: NodeRef::default(),
+            download_link: NodeRef::default(),
             wss,
             _producer: EventBus::bridge(ctx.link().callback(Msg::HandleStateChange)),
         }
@@ -46,13 +101,110 @@ impl Component for App {
                 match sc {
                     Palette(PaletteStateChange::Set(colors)) => {
                         self.palette = colors;
+                        self.gradient = None;
+                    }
+                    Palette(PaletteStateChange::SetGradient(gradient, colors)) => {
+                        self.palette = colors;
+                        self.gradient = Some(gradient);
+                    }
+                    // The palette itself is unchanged.
+                    Palette(PaletteStateChange::Exported(file)) => {
+                        download(&self.download_link, &file);
+                        return false;
+                    }
+                    Palette(PaletteStateChange::SwatchLocks(locks)) => {
+                        self.swatch_locks = locks;
+                    }
+                    Palette(PaletteStateChange::Locked(locked)) => {
+                        self.locked = locked;
+                    }
+                    Palette(PaletteStateChange::Saved(name, colors)) => {
+                        self.saved_palettes.insert(name, colors);
+                    }
+                    Palette(PaletteStateChange::Deleted(name)) => {
+                        self.saved_palettes.remove(&name);
                     }
                     Subscriber(SubscriberStateChange::Added(sub)) => {
-                        self.subscribers.insert(sub.id, sub.cfg);
+                        self.subscribers.insert(sub.id, sub);
                     }
                     Subscriber(SubscriberStateChange::Removed(id)) => {
                         self.subscribers.remove(&id);
                     }
+                    Subscriber(SubscriberStateChange::CurveSet(id, curve)) => {
+                        if let Some(sub) = self.subscribers.get_mut(&id) {
+                            sub.curve = curve;
+                        }
+                    }
+                    Subscriber(SubscriberStateChange::ChannelsSet(id, channels)) => {
+                        if let Some(sub) = self.subscribers.get_mut(&id) {
+                            sub.channels = channels;
+                        }
+                    }
+                    Subscriber(SubscriberStateChange::EncodingSet(id, encoding)) => {
+                        if let Some(sub) = self.subscribers.get_mut(&id) {
+                            sub.encoding = encoding;
+                        }
+                    }
+                    Master(MasterStateChange::Level(level)) => {
+                        self.level = level;
+                    }
+                    Master(MasterStateChange::Blackout(blackout)) => {
+                        self.blackout = blackout;
+                    }
+                    Master(MasterStateChange::Freeze(frozen)) => {
+                        self.frozen = frozen;
+                    }
+                    Cue(CueStateChange::CuesSet(cues)) => {
+                        self.cues = cues;
+                        self.current_cue = None;
+                    }
+                    Cue(CueStateChange::Current(current)) => {
+                        self.current_cue = current;
+                    }
+                    Cue(CueStateChange::Paused(paused)) => {
+                        self.cues_paused = paused;
+                    }
+                    Clock(ClockStateChange::Bpm(bpm)) => {
+                        self.bpm = bpm;
+                    }
+                    Effect(EffectStateChange::Beat(effect)) => {
+                        self.beat_effect = effect;
+                    }
+                    Effect(EffectStateChange::Added(id, effect)) => {
+                        // Refreshes repeat effects we already know about.
+                        self.effects.retain(|(i, _)| *i != id);
+                        self.effects.push((id, effect));
+                    }
+                    Effect(EffectStateChange::Removed(id)) => {
+                        self.effects.retain(|(i, _)| *i != id);
+                    }
+                    Effect(EffectStateChange::Updated(id, effect)) => {
+                        if let Some(entry) = self.effects.iter_mut().find(|(i, _)| *i == id) {
+                            entry.1 = effect;
+                        }
+                    }
+                    Effect(EffectStateChange::Cleared) => {
+                        self.effects.clear();
+                    }
+                    Schedule(ScheduleStateChange::Added(id, scheduled)) => {
+                        self.schedule.retain(|(i, _)| *i != id);
+                        self.schedule.push((id, scheduled));
+                    }
+                    Schedule(ScheduleStateChange::Removed(id)) => {
+                        self.schedule.retain(|(i, _)| *i != id);
+                    }
+                    Schedule(ScheduleStateChange::Updated(id, scheduled)) => {
+                        if let Some(entry) = self.schedule.iter_mut().find(|(i, _)| *i == id) {
+                            entry.1 = scheduled;
+                        }
+                    }
+                    Clients(clients) => {
+                        self.clients = clients;
+                    }
+                    Error(e) => {
+                        log::error!("Server error: {}", e);
+                        self.error = Some(e);
+                    }
                 };
                 true
             }
@@ -60,14 +212,370 @@ impl Component for App {
                 self.wss.tx.try_send(msg).unwrap();
                 false
             }
+            Msg::Upload(upload) => {
+                self.wss.upload_tx.try_send(upload).unwrap();
+                false
+            }
+            Msg::UploadFailed(e) => {
+                log::error!("Upload failed: {}", e);
+                self.error = Some(e);
+                true
+            }
         }
     }
 
     fn view(&self, ctx: &Context<Self>) -> Html {
         // let _ = ctx.link().callback(|_| Msg::SubmitMessage);
 
+        // Extract as many colors as the current palette has.
+        let count = if self.palette.is_empty() {
+            5
+        } else {
+            self.palette.len()
+        };
+        let link = ctx.link().clone();
+        let on_image = Callback::from(move |e: Event| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            let file = match input.files().and_then(|files| files.get(0)) {
+                Some(file) => file,
+                None => return,
+            };
+            link.send_future(async move {
+                match JsFuture::from(file.array_buffer()).await {
+                    Ok(buf) => Msg::Upload(ImageUpload {
+                        extraction: Extraction {
+                            count,
+                            sort: SortBy::Population,
+                        },
+                        image: Uint8Array::new(&buf).to_vec(),
+                    }),
+                    Err(e) => Msg::UploadFailed(format!("{:?}", e)),
+                }
+            });
+        });
+
+        let locks = self.swatch_locks.clone();
+        let on_toggle_lock = ctx.link().callback(move |i: usize| {
+            let locked = !locks.get(i).copied().unwrap_or(false);
+            Msg::Send(ControlMessage::Palette(PaletteControlMessage::LockSwatch(
+                i, locked,
+            )))
+        });
+        let locked = self.locked;
+        let on_lock = ctx.link().callback(move |_| {
+            Msg::Send(ControlMessage::Palette(PaletteControlMessage::Lock(
+                !locked,
+            )))
+        });
+
+        let on_level = ctx.link().callback(|e: InputEvent| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            let level = input.value_as_number() as f32 / 100.;
+            Msg::Send(ControlMessage::Master(MasterControlMessage::Level(level)))
+        });
+        let blackout = self.blackout;
+        let on_blackout = ctx.link().callback(move |_| {
+            Msg::Send(ControlMessage::Master(MasterControlMessage::Blackout(
+                !blackout,
+            )))
+        });
+        let frozen = self.frozen;
+        let on_freeze = ctx.link().callback(move |_| {
+            Msg::Send(ControlMessage::Master(MasterControlMessage::Freeze(
+                !frozen,
+            )))
+        });
+
+        let cue_control = |msg: CueControlMessage| {
+            ctx.link()
+                .callback(move |_| Msg::Send(ControlMessage::Cue(msg.clone())))
+        };
+        let cues: Html = self
+            .cues
+            .iter()
+            .enumerate()
+            .map(|(i, cue)| {
+                let weight = if self.current_cue == Some(i) {
+                    "font-weight:bold"
+                } else {
+                    ""
+                };
+                html! {
+                    <li style={weight} onclick={cue_control(CueControlMessage::Jump(i))}>
+                        { &cue.name }
+                    </li>
+                }
+            })
+            .collect();
+
+        let on_tap = ctx
+            .link()
+            .callback(|_| Msg::Send(ControlMessage::Clock(ClockControlMessage::Tap)));
+        let current_pattern = self.beat_effect.map(|e| e.pattern);
+        let effects: Html = [
+            ("Off", None),
+            ("Rotate", Some(BeatPattern::StepRotate)),
+            ("Chase", Some(BeatPattern::Chase)),
+            ("Strobe", Some(BeatPattern::Strobe)),
+            ("Pulse", Some(BeatPattern::Pulse)),
+        ]
+        .into_iter()
+        .map(|(label, pattern)| {
+            let onclick = ctx.link().callback(move |_| {
+                let effect = pattern.map(|pattern| BeatEffect { pattern, beats: 1. });
+                Msg::Send(ControlMessage::Effect(EffectControlMessage::SetBeat(
+                    effect,
+                )))
+            });
+            html! {
+                <button {onclick} disabled={pattern == current_pattern}>{ label }</button>
+            }
+        })
+        .collect();
+
+        let send_effect = |msg: EffectControlMessage| {
+            ctx.link()
+                .callback(move |_| Msg::Send(ControlMessage::Effect(msg.clone())))
+        };
+        let running: Html = self
+            .effects
+            .iter()
+            .map(|(id, effect)| {
+                html! {
+                    <li>
+                        { format!("{:?} ", effect) }
+                        <button onclick={send_effect(EffectControlMessage::Remove(*id))}>
+                            { "Remove" }
+                        </button>
+                    </li>
+                }
+            })
+            .collect();
+        let add: Html = [
+            (
+                "Breathe",
+                shared::Effect::Breathe {
+                    period: 4.,
+                    depth: 0.6,
+                },
+            ),
+            ("Hue drift", shared::Effect::HueDrift { period: 30. }),
+            (
+                "Sparkle",
+                shared::Effect::Sparkle {
+                    density: 0.5,
+                    decay: 0.3,
+                },
+            ),
+            (
+                "Wave",
+                shared::Effect::Wave {
+                    period: 2.,
+                    wavelength: 4.,
+                    depth: 0.8,
+                },
+            ),
+        ]
+        .into_iter()
+        .map(|(label, effect)| {
+            html! {
+                <button onclick={send_effect(EffectControlMessage::Add(effect))}>
+                    { label }
+                </button>
+            }
+        })
+        .collect();
+
+        let send_palette = |msg: PaletteControlMessage| {
+            ctx.link()
+                .callback(move |_| Msg::Send(ControlMessage::Palette(msg.clone())))
+        };
+        let saved: Html = self
+            .saved_palettes
+            .keys()
+            .map(|name| {
+                html! {
+                    <li>
+                        { format!("{} ", name) }
+                        <button onclick={send_palette(PaletteControlMessage::Recall(name.clone()))}>
+                            { "Recall" }
+                        </button>
+                        <button onclick={send_palette(PaletteControlMessage::Delete(name.clone()))}>
+                            { "Delete" }
+                        </button>
+                    </li>
+                }
+            })
+            .collect();
+        let link = ctx.link().clone();
+        let on_import = Callback::from(move |e: Event| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            let file = match input.files().and_then(|files| files.get(0)) {
+                Some(file) => file,
+                None => return,
+            };
+            let name = file.name();
+            let format = match name
+                .rsplit_once('.')
+                .and_then(|(_, ext)| PaletteFormat::from_extension(ext))
+            {
+                Some(format) => format,
+                None => {
+                    link.send_message(Msg::UploadFailed(format!(
+                        "unknown palette file type \"{}\"",
+                        name
+                    )));
+                    return;
+                }
+            };
+            link.send_future(async move {
+                match JsFuture::from(file.array_buffer()).await {
+                    Ok(buf) => Msg::Send(ControlMessage::Palette(PaletteControlMessage::Import(
+                        PaletteFile {
+                            format,
+                            data: Uint8Array::new(&buf).to_vec(),
+                        },
+                    ))),
+                    Err(e) => Msg::UploadFailed(format!("{:?}", e)),
+                }
+            });
+        });
+        let accept = PaletteFormat::ALL
+            .iter()
+            .map(|f| format!(".{}", f.extension()))
+            .collect::<Vec<_>>()
+            .join(",");
+        let export_format = self.export_format.clone();
+        let on_export = ctx.link().callback(move |_| {
+            let format = export_format.cast::<HtmlSelectElement>().unwrap().value();
+            // Every option is a format's extension.
+            let format = format.parse().unwrap();
+            Msg::Send(ControlMessage::Palette(PaletteControlMessage::Export(
+                format,
+            )))
+        });
+        let formats: Html = PaletteFormat::ALL
+            .iter()
+            .map(|f| html! { <option value={f.extension()}>{ f.extension() }</option> })
+            .collect();
+
+        let save_name = self.save_name.clone();
+        let on_save = ctx.link().callback(move |_| {
+            let name = save_name.cast::<HtmlInputElement>().unwrap().value();
+            Msg::Send(ControlMessage::Palette(PaletteControlMessage::Save(name)))
+        });
+
         html! {
-            <Palette colors={self.palette.clone()} />
+            <>
+                <Palette
+                    colors={self.palette.clone()}
+                    locks={self.swatch_locks.clone()}
+                    {on_toggle_lock}
+                />
+                <GradientEditor
+                    gradient={self.gradient.clone()}
+                    palette={self.palette.clone()}
+                    on_send={ctx.link().callback(Msg::Send)}
+                />
+                <div>
+                    <label>
+                        <input type="checkbox" checked={self.locked} onclick={on_lock} />
+                        { " Lock palette (click a swatch to lock it)" }
+                    </label>
+                </div>
+                <div>
+                    <label>
+                        { "Master " }
+                        <input
+                            type="range"
+                            min="0"
+                            max="100"
+                            value={(self.level * 100.).round().to_string()}
+                            oninput={on_level}
+                        />
+                        { format!(" {:.0}%", self.level * 100.) }
+                    </label>
+                    <label>
+                        <input type="checkbox" checked={self.blackout} onclick={on_blackout} />
+                        { " Blackout" }
+                    </label>
+                    <label>
+                        <input type="checkbox" checked={self.frozen} onclick={on_freeze} />
+                        { " Freeze" }
+                    </label>
+                </div>
+                <div>
+                    <button onclick={cue_control(CueControlMessage::Back)}>{ "Back" }</button>
+                    <button onclick={cue_control(CueControlMessage::Go)}>{ "Go" }</button>
+                    <button onclick={cue_control(CueControlMessage::Pause(!self.cues_paused))}>
+                        { if self.cues_paused { "Resume" } else { "Pause" } }
+                    </button>
+                    <ol start="0">{ cues }</ol>
+                </div>
+                <div>
+                    <button onclick={on_tap}>{ "Tap" }</button>
+                    { format!(" {:.1} BPM ", self.bpm) }
+                    { effects }
+                </div>
+                <div>
+                    { "Add effect: " }
+                    { add }
+                    <ul>{ running }</ul>
+                </div>
+                <div>
+                    <input type="text" placeholder="Palette name" ref={self.save_name.clone()} />
+                    <button onclick={on_save}>{ "Save palette" }</button>
+                    <ul>{ saved }</ul>
+                </div>
+                <Schedule
+                    entries={self.schedule.clone()}
+                    palettes={self.saved_palettes.keys().cloned().collect::<Vec<_>>()}
+                    on_send={ctx.link().callback(Msg::Send)}
+                />
+                <div>
+                    <label>{ "Import palette: " }</label>
+                    <input type="file" {accept} onchange={on_import} />
+                    <select ref={self.export_format.clone()}>{ formats }</select>
+                    <button onclick={on_export}>{ "Export palette" }</button>
+                    <a ref={self.download_link.clone()} style="display:none" />
+                </div>
+                <div>
+                    <label>{ "Palette from image: " }</label>
+                    <input type="file" accept="image/png,image/jpeg" onchange={on_image} />
+                </div>
+                <p>
+                    { format!(
+                        "{} editing, {} observing",
+                        self.clients.editors, self.clients.observers
+                    ) }
+                </p>
+                if let Some(e) = &self.error {
+                    <p style="color:red">{ e }</p>
+                }
+            </>
         }
     }
 }
+
+/// Save an exported palette file through the browser's download prompt.
+fn download(link: &NodeRef, file: &PaletteFile) {
+    let anchor = match link.cast::<HtmlAnchorElement>() {
+        Some(anchor) => anchor,
+        None => return,
+    };
+    let parts = js_sys::Array::of1(&Uint8Array::from(file.data.as_slice()));
+    let url = match Blob::new_with_u8_array_sequence(&parts)
+        .and_then(|blob| Url::create_object_url_with_blob(&blob))
+    {
+        Ok(url) => url,
+        Err(e) => {
+            log::error!("Failed to download exported palette: {:?}", e);
+            return;
+        }
+    };
+    anchor.set_href(&url);
+    anchor.set_download(&format!("palette.{}", file.format.extension()));
+    anchor.click();
+    // The download has started, so the URL can go.
+    let _ = Url::revoke_object_url(&url);
+}
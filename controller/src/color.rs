@@ -4,32 +4,66 @@ use yew::prelude::*;
 #[derive(Clone, Properties, PartialEq)]
 pub struct ColorProps {
     pub color: ColorType,
+    #[prop_or_default]
+    pub locked: bool,
+    #[prop_or_default]
+    pub onclick: Callback<MouseEvent>,
 }
 
 #[function_component(Color)]
-pub fn color(ColorProps { color }: &ColorProps) -> Html {
+pub fn color(
+    ColorProps {
+        color,
+        locked,
+        onclick,
+    }: &ColorProps,
+) -> Html {
     let (r, g, b) = color.as_u8();
+    // Outline locked swatches without changing their size.
+    let outline = if *locked {
+        "outline:4px dashed white;outline-offset:-8px;"
+    } else {
+        ""
+    };
     let style = format!(
-        "width:100px;height:100px;display:inline-block;margin:0px;background: rgb({},{},{})",
-        r, g, b
+        "width:100px;height:100px;display:inline-block;margin:0px;{}background: rgb({},{},{})",
+        outline, r, g, b
     );
     html! {
-        <div style={style} />
+        <div style={style} title={color.to_hex()} onclick={onclick.clone()} />
     }
 }
 
 #[derive(Clone, Properties, PartialEq)]
 pub struct PaletteProps {
     pub colors: Vec<ColorType>,
+    /// Lock flags by index; missing flags are unlocked.
+    #[prop_or_default]
+    pub locks: Vec<bool>,
+    /// Called with the index of a clicked swatch.
+    #[prop_or_default]
+    pub on_toggle_lock: Callback<usize>,
 }
 
 #[function_component(Palette)]
-pub fn palette(PaletteProps { colors }: &PaletteProps) -> Html {
+pub fn palette(
+    PaletteProps {
+        colors,
+        locks,
+        on_toggle_lock,
+    }: &PaletteProps,
+) -> Html {
     colors
         .iter()
-        .map(|color| {
+        .enumerate()
+        .map(|(i, color)| {
+            let onclick = on_toggle_lock.reform(move |_| i);
             html! {
-                <Color color={*color} />
+                <Color
+                    color={*color}
+                    locked={locks.get(i).copied().unwrap_or(false)}
+                    {onclick}
+                />
             }
         })
         .collect()
@@ -0,0 +1,136 @@
+use shared::{Color, ColorSpace, ControlMessage, Gradient, GradientStop, PaletteControlMessage};
+use web_sys::HtmlInputElement;
+use yew::prelude::*;
+
+#[derive(Clone, Properties, PartialEq)]
+pub struct GradientProps {
+    /// The gradient the palette was sampled from, if any.
+    pub gradient: Option<Gradient>,
+    /// The current palette, which a new gradient starts from.
+    pub palette: Vec<Color>,
+    pub on_send: Callback<ControlMessage>,
+}
+
+/// Edit the stops of the gradient the palette is sampled from. Every edit
+/// resamples the palette.
+#[function_component(GradientEditor)]
+pub fn gradient_editor(
+    GradientProps {
+        gradient,
+        palette,
+        on_send,
+    }: &GradientProps,
+) -> Html {
+    // Without a gradient, offer one between the ends of the palette.
+    let gradient = gradient.clone().unwrap_or_else(|| Gradient {
+        stops: vec![
+            GradientStop {
+                position: 0.,
+                color: palette.first().copied().unwrap_or(Color::BLACK),
+            },
+            GradientStop {
+                position: 1.,
+                color: palette.last().copied().unwrap_or(Color::rgb(1., 1., 1.)),
+            },
+        ],
+        space: ColorSpace::default(),
+        count: palette.len().max(2),
+    });
+
+    let stops: Html = gradient
+        .stops
+        .iter()
+        .enumerate()
+        .map(|(i, stop)| {
+            let on_color = on_change(&gradient, on_send, move |g, value| {
+                if let Ok(color) = Color::from_hex(&value) {
+                    g.stops[i].color = color;
+                }
+            });
+            let on_position = on_change(&gradient, on_send, move |g, value| {
+                if let Ok(percent) = value.parse::<f32>() {
+                    g.stops[i].position = percent / 100.;
+                }
+            });
+            let on_remove = on_click(&gradient, on_send, move |g| {
+                g.stops.remove(i);
+            });
+            html! {
+                <li>
+                    <input type="color" value={stop.color.to_hex()} onchange={on_color} />
+                    <input
+                        type="range"
+                        min="0"
+                        max="100"
+                        value={(stop.position * 100.).round().to_string()}
+                        onchange={on_position}
+                    />
+                    <button onclick={on_remove} disabled={gradient.stops.len() < 2}>
+                        { "Remove" }
+                    </button>
+                </li>
+            }
+        })
+        .collect();
+
+    let on_add = on_click(&gradient, on_send, |g| {
+        // Split the gradient halfway, without changing how it looks.
+        let color = g.sample(0.5);
+        g.stops.push(GradientStop {
+            position: 0.5,
+            color,
+        });
+    });
+    let on_count = on_change(&gradient, on_send, |g, value| {
+        if let Ok(count) = value.parse() {
+            g.count = count;
+        }
+    });
+    let on_apply = on_click(&gradient, on_send, |_| ());
+
+    html! {
+        <div>
+            { "Gradient stops:" }
+            <ul>{ stops }</ul>
+            <button onclick={on_add}>{ "Add stop" }</button>
+            { " Colors: " }
+            <input type="number" min="1" value={gradient.count.to_string()} onchange={on_count} />
+            <button onclick={on_apply}>{ "Set palette from gradient" }</button>
+        </div>
+    }
+}
+
+/// Make a callback that edits the gradient with the changed input's value
+/// and sends the result.
+fn on_change(
+    gradient: &Gradient,
+    on_send: &Callback<ControlMessage>,
+    edit: impl Fn(&mut Gradient, String) + 'static,
+) -> Callback<Event> {
+    let gradient = gradient.clone();
+    on_send.reform(move |e: Event| {
+        let input: HtmlInputElement = e.target_unchecked_into();
+        let mut gradient = gradient.clone();
+        edit(&mut gradient, input.value());
+        set_gradient(gradient)
+    })
+}
+
+/// Make a callback that edits the gradient when clicked and sends the
+/// result.
+fn on_click(
+    gradient: &Gradient,
+    on_send: &Callback<ControlMessage>,
+    edit: impl Fn(&mut Gradient) + 'static,
+) -> Callback<MouseEvent> {
+    let gradient = gradient.clone();
+    on_send.reform(move |_| {
+        let mut gradient = gradient.clone();
+        edit(&mut gradient);
+        set_gradient(gradient)
+    })
+}
+
+fn set_gradient(gradient: Gradient) -> ControlMessage {
+    ControlMessage::Palette(PaletteControlMessage::SetGradient(gradient))
+}
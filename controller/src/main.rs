@@ -1,6 +1,8 @@
 mod app;
 mod color;
 mod event_bus;
+mod gradient;
+mod schedule;
 mod websocket;
 
 use app::App;
@@ -0,0 +1,88 @@
+use shared::{
+    ControlMessage, PaletteControlMessage, ScheduleControlMessage, ScheduleId, Scheduled, Trigger,
+};
+use web_sys::{HtmlInputElement, HtmlSelectElement};
+use yew::prelude::*;
+
+#[derive(Clone, Properties, PartialEq)]
+pub struct ScheduleProps {
+    pub entries: Vec<(ScheduleId, Scheduled)>,
+    /// Names of saved palettes that can be scheduled for recall.
+    pub palettes: Vec<String>,
+    pub on_send: Callback<ControlMessage>,
+}
+
+/// List scheduled actions, and schedule recalls of saved palettes.
+#[function_component(Schedule)]
+pub fn schedule(
+    ScheduleProps {
+        entries,
+        palettes,
+        on_send,
+    }: &ScheduleProps,
+) -> Html {
+    let kind = use_node_ref();
+    let when = use_node_ref();
+    let palette = use_node_ref();
+
+    let rows: Html = entries
+        .iter()
+        .map(|(id, scheduled)| {
+            let id = *id;
+            let toggled = Scheduled {
+                enabled: !scheduled.enabled,
+                ..scheduled.clone()
+            };
+            let on_toggle = on_send.reform(move |_| {
+                ControlMessage::Schedule(ScheduleControlMessage::Update(id, toggled.clone()))
+            });
+            let on_remove = on_send
+                .reform(move |_| ControlMessage::Schedule(ScheduleControlMessage::Remove(id)));
+            let when = match &scheduled.trigger {
+                Trigger::At(at) => format!("at {}", at),
+                Trigger::Cron(expr) => format!("cron {}", expr),
+            };
+            html! {
+                <li>
+                    <input type="checkbox" checked={scheduled.enabled} onclick={on_toggle} />
+                    { format!(" {} ({}) ", scheduled.name, when) }
+                    <button onclick={on_remove}>{ "Remove" }</button>
+                </li>
+            }
+        })
+        .collect();
+
+    let on_add = {
+        let (kind, when, palette) = (kind.clone(), when.clone(), palette.clone());
+        on_send.reform(move |_| {
+            let when = when.cast::<HtmlInputElement>().unwrap().value();
+            let name = palette.cast::<HtmlSelectElement>().unwrap().value();
+            let trigger = match kind.cast::<HtmlSelectElement>().unwrap().value().as_str() {
+                "cron" => Trigger::Cron(when),
+                _ => Trigger::At(when),
+            };
+            ControlMessage::Schedule(ScheduleControlMessage::Add(Scheduled {
+                name: format!("Recall {}", name),
+                trigger,
+                action: Box::new(ControlMessage::Palette(PaletteControlMessage::Recall(name))),
+                enabled: true,
+            }))
+        })
+    };
+
+    html! {
+        <div>
+            <ul>{ rows }</ul>
+            { "Recall " }
+            <select ref={palette}>
+                { for palettes.iter().map(|name| html! { <option>{ name }</option> }) }
+            </select>
+            <select ref={kind}>
+                <option value="at">{ "at (YYYY-MM-DD HH:MM)" }</option>
+                <option value="cron">{ "cron (sec min hour day month weekday)" }</option>
+            </select>
+            <input type="text" ref={when} />
+            <button onclick={on_add} disabled={palettes.is_empty()}>{ "Schedule" }</button>
+        </div>
+    }
+}
@@ -1,30 +1,42 @@
 use futures::{channel::mpsc::Sender, SinkExt, StreamExt};
 use reqwasm::websocket::{futures::WebSocket, Message};
 
-use shared::ControlMessage;
+use shared::{ControlMessage, ImageUpload};
 use wasm_bindgen_futures::spawn_local;
 use yew_agent::Dispatched;
 
 use crate::event_bus::EventBus;
 
+/// Where to find the server if the page was not loaded from it, such as
+/// when opened as a file.
+const DEFAULT_HOST: &str = "127.0.0.1:8081";
+
 pub struct WebsocketService {
     pub tx: Sender<ControlMessage>,
+    /// Image uploads are sent as binary messages.
+    pub upload_tx: Sender<ImageUpload>,
 }
 
 impl WebsocketService {
     pub fn new() -> Self {
-        let ws = WebSocket::open("ws://127.0.0.1:8081").unwrap();
+        let ws = WebSocket::open(&server_url()).unwrap();
 
         let (mut write, mut read) = ws.split();
 
-        let (in_tx, mut in_rx) = futures::channel::mpsc::channel::<ControlMessage>(1000);
+        let (in_tx, in_rx) = futures::channel::mpsc::channel::<ControlMessage>(1000);
+        let (upload_tx, upload_rx) = futures::channel::mpsc::channel::<ImageUpload>(10);
         let mut event_bus = EventBus::dispatcher();
 
         spawn_local(async move {
-            while let Some(msg) = in_rx.next().await {
-                log::debug!("got event from channel! {:?}", msg);
-                let s = serde_json::to_string(&msg).unwrap();
-                write.send(Message::Text(s)).await.unwrap();
+            let mut outgoing = futures::stream::select(
+                in_rx.map(|msg| {
+                    log::debug!("got event from channel! {:?}", msg);
+                    Message::Text(serde_json::to_string(&msg).unwrap())
+                }),
+                upload_rx.map(|upload| Message::Bytes(upload.to_bytes().unwrap())),
+            );
+            while let Some(msg) = outgoing.next().await {
+                write.send(msg).await.unwrap();
             }
         });
 
@@ -58,6 +70,33 @@ impl WebsocketService {
             log::debug!("WebSocket Closed");
         });
 
-        Self { tx: in_tx }
+        Self {
+            tx: in_tx,
+            upload_tx,
+        }
     }
 }
+
+/// The server accepts websockets at the address it serves the app from,
+/// securely if the app was loaded securely. The page's query string is
+/// passed along, so opening the app with `?token=<token>` authenticates
+/// with the server.
+fn server_url() -> String {
+    let location = web_sys::window().map(|window| window.location());
+    let location = location.as_ref();
+    let host = location
+        .and_then(|location| location.host().ok())
+        .filter(|host| !host.is_empty());
+    let secure = location
+        .and_then(|location| location.protocol().ok())
+        .map_or(false, |protocol| protocol == "https:");
+    let query = location
+        .and_then(|location| location.search().ok())
+        .unwrap_or_default();
+    format!(
+        "{}://{}/{}",
+        if secure { "wss" } else { "ws" },
+        host.as_deref().unwrap_or(DEFAULT_HOST),
+        query
+    )
+}
@@ -0,0 +1,80 @@
+use std::fs;
+use std::io::Write;
+use std::path::{Component, Path, PathBuf};
+
+use log::{debug, error};
+
+/// Respond to a plain HTTP request with a file from the controller app's
+/// directory. The root path serves index.html.
+pub fn serve(mut stream: impl Write, dir: Option<&Path>, method: &str, uri: &str) {
+    // The query string may hold a token, so keep it out of the logs.
+    let path = uri.split(['?', '#']).next().unwrap_or_default();
+    debug!("HTTP {} {}", method, path);
+    let (status, content_type, body) = match (method, dir) {
+        ("GET" | "HEAD", Some(dir)) => match read(dir, path) {
+            Some((path, body)) => (200, content_type(&path), body),
+            None => not_found(),
+        },
+        ("GET" | "HEAD", None) => not_found(),
+        _ => (405, "text/plain", b"method not allowed".to_vec()),
+    };
+    let reason = match status {
+        200 => "OK",
+        404 => "Not Found",
+        _ => "Method Not Allowed",
+    };
+    let head = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        reason,
+        content_type,
+        body.len()
+    );
+    let result = stream.write_all(head.as_bytes()).and_then(|_| {
+        if method == "HEAD" {
+            Ok(())
+        } else {
+            stream.write_all(&body)
+        }
+    });
+    if let Err(e) = result {
+        error!("Failed to send HTTP response: {}.", e);
+    }
+}
+
+fn not_found() -> (u16, &'static str, Vec<u8>) {
+    (404, "text/plain", b"not found".to_vec())
+}
+
+/// Read the file a request path refers to, if it lies within the
+/// directory.
+fn read(dir: &Path, path: &str) -> Option<(PathBuf, Vec<u8>)> {
+    let relative = Path::new(path.trim_start_matches('/'));
+    if !relative
+        .components()
+        .all(|c| matches!(c, Component::Normal(_)))
+    {
+        return None;
+    }
+    let mut path = dir.join(relative);
+    if path.is_dir() {
+        path.push("index.html");
+    }
+    let body = fs::read(&path).ok()?;
+    Some((path, body))
+}
+
+fn content_type(path: &Path) -> &'static str {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("html") => "text/html; charset=utf-8",
+        Some("js") => "text/javascript",
+        // Browsers only compile wasm as it streams in with this type.
+        Some("wasm") => "application/wasm",
+        Some("css") => "text/css",
+        Some("json") => "application/json",
+        Some("svg") => "image/svg+xml",
+        Some("png") => "image/png",
+        Some("ico") => "image/x-icon",
+        _ => "application/octet-stream",
+    }
+}
@@ -0,0 +1,78 @@
+use shared::{ControlMessage, PaletteControlMessage};
+
+/// What a connected client may do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    /// Receive state without changing it.
+    Observer,
+    /// Full control.
+    Editor,
+}
+
+impl Role {
+    /// True if a client with this role may send a control message.
+    pub fn allows(&self, msg: &ControlMessage) -> bool {
+        match self {
+            Role::Editor => true,
+            Role::Observer => matches!(
+                msg,
+                ControlMessage::Refresh | ControlMessage::Palette(PaletteControlMessage::Export(_))
+            ),
+        }
+    }
+}
+
+/// Tokens that grant clients a role. Without an editor token, every client
+/// may edit without presenting one.
+#[derive(Clone)]
+pub struct Auth {
+    editor: Option<String>,
+    viewer: Option<String>,
+}
+
+impl Auth {
+    pub fn new(editor: Option<String>, viewer: Option<String>) -> Self {
+        Self { editor, viewer }
+    }
+
+    /// Return the role a token grants, or None if the client may not
+    /// connect.
+    pub fn role(&self, token: Option<&str>) -> Option<Role> {
+        let editor = match &self.editor {
+            Some(editor) => editor,
+            None => return Some(Role::Editor),
+        };
+        let token = token?;
+        if matches(token, editor) {
+            Some(Role::Editor)
+        } else if self.viewer.as_deref().is_some_and(|v| matches(token, v)) {
+            Some(Role::Observer)
+        } else {
+            None
+        }
+    }
+}
+
+/// Return a parameter from a request URI's query string, such as the
+/// token in `?token=<token>`. Values are compared as written, so tokens
+/// should be URL-safe.
+pub fn query_param<'a>(uri: &'a str, name: &str) -> Option<&'a str> {
+    let (_, query) = uri.split_once('?')?;
+    query
+        .split('&')
+        .find_map(|param| match param.split_once('=') {
+            Some((key, value)) if key == name => Some(value),
+            _ => None,
+        })
+}
+
+/// Compare tokens in time that depends only on their lengths, so a client
+/// cannot guess a token a byte at a time.
+fn matches(token: &str, expected: &str) -> bool {
+    token.len() == expected.len()
+        && token
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
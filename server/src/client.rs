@@ -1,57 +1,98 @@
 use log::{debug, error, info, warn};
-use shared::{ControlMessage, StateChange};
+use shared::{ClientCounts, ControlMessage, ImageUpload, PaletteControlMessage, StateChange};
 use std::error::Error;
-use std::net::TcpStream;
-use std::sync::mpsc::Sender;
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{channel, Sender};
 use std::sync::{Arc, Mutex};
 use std::thread;
-use websocket::server::upgrade::WsUpgrade;
-use websocket::sync::server::upgrade::Buffer;
-use websocket::sync::{Reader, Server, Writer};
+use websocket::server::upgrade::sync::IntoWs;
+use websocket::stream::sync::ReadWritePair;
+use websocket::sync::{Reader, Writer};
 use websocket::OwnedMessage;
 
-type Senders = Arc<Mutex<Vec<Writer<TcpStream>>>>;
+use crate::assets;
+use crate::auth::{query_param, Auth, Role};
+use crate::control::Request;
+use crate::extract;
+use crate::tls::{Connection, Tls};
 
-/// Handle client communication via websockets.
+type Senders = Arc<Mutex<Vec<Connected>>>;
+
+/// A connected websocket client.
+struct Connected {
+    id: u64,
+    role: Role,
+    writer: Writer<Connection>,
+}
+
+/// Handle client communication via websockets. Plain HTTP requests to the
+/// same address are served the controller app.
+///
+/// Clients present a token as `?token=<token>` in the websocket URL, which
+/// decides whether they may connect and what they may change. Any client
+/// may connect with `?mode=observer` to only receive state; control
+/// messages from observers are answered with an error.
 pub struct Clients {
     senders: Senders,
 }
 
+/// Everything needed to handle a new connection.
+#[derive(Clone)]
+struct Listener {
+    senders: Senders,
+    assets: Option<PathBuf>,
+    auth: Auth,
+    tls: Option<Tls>,
+    send: Sender<Request>,
+    next_id: Arc<AtomicU64>,
+}
+
 impl Clients {
-    pub fn new(send: Sender<ControlMessage>) -> Result<Self, Box<dyn Error>> {
+    pub fn new(
+        addr: SocketAddr,
+        assets: Option<PathBuf>,
+        auth: Auth,
+        tls: Option<Tls>,
+        send: Sender<Request>,
+    ) -> Result<Self, Box<dyn Error>> {
         let senders = Arc::new(Mutex::new(Vec::new()));
         let manager = Self {
             senders: senders.clone(),
         };
         // Create the websocket server and launch a thread to handle connections.
-        let server = Server::bind("127.0.0.1:8081")?;
+        let server = TcpListener::bind(addr)?;
+        info!(
+            "Accepting websocket clients on {}{}.",
+            addr,
+            if tls.is_some() { " using TLS" } else { "" }
+        );
+        let listener = Listener {
+            senders,
+            assets,
+            auth,
+            tls,
+            send,
+            next_id: Arc::new(AtomicU64::new(0)),
+        };
 
         thread::spawn(move || {
-            for req in server
-                .map(|r| {
-                    if let Err(ref e) = r {
-                        error!("Websocket error: {:?}.", e);
-                    }
-                    r
-                })
-                .filter_map(Result::ok)
-            {
-                let (reader, writer) = match handle_upgrade_request(req) {
-                    Ok(c) => c,
+            for sock in server.incoming() {
+                let sock = match sock {
+                    Ok(sock) => sock,
                     Err(e) => {
-                        error!("Failed to handle websocket upgrade request: {}.", e);
+                        error!("Websocket connection error: {}.", e);
                         continue;
                     }
                 };
-                // Add the writer to the collection of senders.
-                // Use a new scope to immediately release the lock.
-                {
-                    senders.lock().unwrap().push(writer);
-                }
-                let send_copy = send.clone();
-                // Spawn a thread to handle incoming requests.
+                let listener = listener.clone();
+                // Each connection gets its own thread, so a slow handshake
+                // doesn't hold up the rest.
                 thread::spawn(move || {
-                    handle_messages(reader, send_copy);
+                    if let Err(e) = listener.handle_connection(sock) {
+                        error!("Websocket error: {}.", e);
+                    }
                 });
             }
         });
@@ -60,64 +101,197 @@ impl Clients {
 
     pub fn send_state_update(&self, msg: &StateChange) -> Result<(), Box<dyn Error>> {
         debug!("Sending state update to clients: {:?}", *msg);
-        let serialized = OwnedMessage::Text(serde_json::to_string(&msg)?);
-        for sender in self.senders.lock().unwrap().iter_mut() {
-            if let Err(e) = sender.send_message(&serialized) {
-                // FIXME: how do we know when a client has hung up?
-                // Need to identify this condition and boot the sender from the
-                // collection.
-                error!("Websocket send error: {}.", e);
-            }
-        }
-        Ok(())
+        send_to(&self.senders, msg, |_| true)
+    }
+
+    /// Count the connected clients by role.
+    pub fn current_state(&self) -> ClientCounts {
+        counts(&self.senders)
     }
 }
 
-fn handle_upgrade_request(
-    request: WsUpgrade<TcpStream, Option<Buffer>>,
-) -> Result<(Reader<TcpStream>, Writer<TcpStream>), Box<dyn Error>> {
-    info!("{:?}", request.protocols());
-    let client = request.accept().map_err(|(_, e)| e)?;
+/// Send a state change to each connected client that matches a filter.
+fn send_to(
+    senders: &Senders,
+    msg: &StateChange,
+    filter: impl Fn(&Connected) -> bool,
+) -> Result<(), Box<dyn Error>> {
+    let serialized = OwnedMessage::Text(serde_json::to_string(&msg)?);
+    for client in senders.lock().unwrap().iter_mut().filter(|c| filter(c)) {
+        // A client that has hung up is removed once its reader notices.
+        if let Err(e) = client.writer.send_message(&serialized) {
+            error!("Websocket send error: {}.", e);
+        }
+    }
+    Ok(())
+}
 
-    Ok(client.split()?)
+fn counts(senders: &Senders) -> ClientCounts {
+    let mut counts = ClientCounts::default();
+    for client in senders.lock().unwrap().iter() {
+        match client.role {
+            Role::Editor => counts.editors += 1,
+            Role::Observer => counts.observers += 1,
+        }
+    }
+    counts
 }
 
-/// Handle incoming messages from a websocket reader, deserialize, and forward.
-fn handle_messages(mut reader: Reader<TcpStream>, send: Sender<ControlMessage>) {
-    for message_result in reader.incoming_messages() {
-        // FIXME: will this terminate if the socket is closed?
-        let message = match message_result {
-            Ok(m) => m,
-            Err(e) => {
-                warn!("Websocket receive error: {}.", e);
-                continue;
-            }
+impl Listener {
+    /// Upgrade a new connection to a websocket and handle its messages,
+    /// or serve the controller app if it is a plain HTTP request.
+    fn handle_connection(self, sock: TcpStream) -> Result<(), Box<dyn Error>> {
+        let conn = match &self.tls {
+            Some(tls) => tls.accept(sock)?,
+            None => Connection::Plain(sock),
         };
-        let contents = match message {
-            OwnedMessage::Close(_) => {
-                info!("Websocket close, terminating listener thread.");
-                return;
+        let req = match ReadWritePair(conn.try_clone()?, conn).into_ws() {
+            Ok(req) => req,
+            Err((stream, Some(request), _, _)) => {
+                let method = request.subject.0.to_string();
+                let uri = request.subject.1.to_string();
+                assets::serve(stream.1, self.assets.as_deref(), &method, &uri);
+                return Ok(());
             }
-            OwnedMessage::Text(t) => t,
-            other => {
-                warn!("Unhandled websocket message type: {:?}", other);
-                continue;
+            Err((_, None, _, e)) => return Err(e.into()),
+        };
+        let uri = req.uri();
+        let role = match self.auth.role(query_param(&uri, "token")) {
+            Some(role) => role,
+            None => {
+                warn!("Rejecting websocket client without a valid token.");
+                req.reject().map_err(|(_, e)| e)?;
+                return Ok(());
             }
         };
-        let control_msg: ControlMessage = match serde_json::from_str(&contents) {
-            Ok(m) => m,
-            Err(e) => {
-                error!(
-                    "Failed to deserialize websocket message: {}.\n\nMessage body:\n{}",
-                    e, contents
-                );
+        // Editors may choose to only observe, such as on a preview monitor.
+        let role = match query_param(&uri, "mode") {
+            Some("observer") => Role::Observer,
+            _ => role,
+        };
+        info!("{:?} connected: {:?}", role, req.protocols());
+        let (reader, writer) = req.accept().map_err(|(_, e)| e)?.split()?;
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        // Add the writer to the collection of senders.
+        // Use a new scope to immediately release the lock.
+        {
+            self.senders
+                .lock()
+                .unwrap()
+                .push(Connected { id, role, writer });
+        }
+        self.send_counts();
+        self.handle_messages(reader, id, role);
+        self.senders.lock().unwrap().retain(|c| c.id != id);
+        self.send_counts();
+        Ok(())
+    }
+
+    /// Tell every client how many clients are connected.
+    fn send_counts(&self) {
+        let msg = StateChange::Clients(counts(&self.senders));
+        if let Err(e) = send_to(&self.senders, &msg, |_| true) {
+            error!("Failed to send client counts: {}.", e);
+        }
+    }
+
+    /// Tell one client that its control message was rejected.
+    fn send_error(&self, id: u64, message: String) {
+        if let Err(e) = send_to(&self.senders, &StateChange::Error(message), |c| c.id == id) {
+            error!("Failed to send rejection: {}.", e);
+        }
+    }
+
+    /// Handle incoming messages from a websocket reader, deserialize, and
+    /// forward, until the client disconnects.
+    fn handle_messages(&self, mut reader: Reader<Connection>, id: u64, role: Role) {
+        for message_result in reader.incoming_messages() {
+            let message = match message_result {
+                Ok(m) => m,
+                Err(e) => {
+                    info!("Websocket receive error, disconnecting client: {}.", e);
+                    return;
+                }
+            };
+            let control_msg = match message {
+                OwnedMessage::Close(_) => {
+                    info!("Websocket close, terminating listener thread.");
+                    return;
+                }
+                OwnedMessage::Text(contents) => {
+                    let control_msg: ControlMessage = match serde_json::from_str(&contents) {
+                        Ok(m) => m,
+                        Err(e) => {
+                            error!(
+                                "Failed to deserialize websocket message: {}.\n\nMessage body:\n{}",
+                                e, contents
+                            );
+                            continue;
+                        }
+                    };
+                    info!("Got control message: {:?}", control_msg);
+                    control_msg
+                }
+                // Binary messages are image uploads.
+                OwnedMessage::Binary(data) => match ImageUpload::from_bytes(&data) {
+                    Ok(upload) => {
+                        info!(
+                            "Got {} byte image upload: {:?}",
+                            upload.image.len(),
+                            upload.extraction
+                        );
+                        ControlMessage::Palette(PaletteControlMessage::Extract(upload))
+                    }
+                    Err(e) => {
+                        error!("Failed to parse image upload header: {}.", e);
+                        continue;
+                    }
+                },
+                other => {
+                    warn!("Unhandled websocket message type: {:?}", other);
+                    continue;
+                }
+            };
+            if !role.allows(&control_msg) {
+                warn!("Rejecting control message from observer: {:?}", control_msg);
+                self.send_error(id, "observers cannot make changes".to_string());
                 continue;
             }
-        };
-        info!("Got control message: {:?}", control_msg);
-        if send.send(control_msg).is_err() {
-            info!("Terminating websocket receiver thread.");
-            return;
+            let control_msg = match extract::resolve(control_msg) {
+                Ok(msg) => msg,
+                Err(e) => {
+                    self.send_error(id, e.to_string());
+                    continue;
+                }
+            };
+            if let Err(e) = self.forward(id, control_msg) {
+                info!("Terminating websocket receiver thread: {}.", e);
+                return;
+            }
+        }
+    }
+
+    /// Pass a control message to the dispatcher. A refresh or export is
+    /// only of interest to this client, so the dispatcher replies with the
+    /// result for this client alone rather than broadcasting it.
+    fn forward(&self, id: u64, msg: ControlMessage) -> Result<(), Box<dyn Error>> {
+        let private = matches!(
+            msg,
+            ControlMessage::Refresh | ControlMessage::Palette(PaletteControlMessage::Export(_))
+        );
+        if !private {
+            self.send.send(msg.into())?;
+            return Ok(());
+        }
+        let (reply, recv) = channel();
+        self.send.send(Request {
+            msg,
+            reply: Some(reply),
+            depth: 0,
+        })?;
+        for sc in recv.recv()? {
+            send_to(&self.senders, &sc, |c| c.id == id)?;
         }
+        Ok(())
     }
 }
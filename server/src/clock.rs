@@ -0,0 +1,230 @@
+use std::error::Error;
+use std::time::{Duration, Instant};
+
+use shared::{ClockControlMessage, ClockStateChange};
+use simple_error::bail;
+
+pub const MIN_BPM: f32 = 20.;
+pub const MAX_BPM: f32 = 300.;
+
+/// Taps further apart than this start a new tempo.
+const TAP_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// The most recent taps that are averaged to find the tempo.
+const MAX_TAPS: usize = 8;
+
+/// External beats further apart than this are treated as a new source, and
+/// the clock snaps to them rather than easing toward them. This leaves room
+/// for beats at MIN_BPM to arrive late, so sources that slow can be followed.
+const BEAT_TIMEOUT: Duration =
+    Duration::from_millis((60_000. / (MIN_BPM * (1. - TEMPO_TOLERANCE))) as u64);
+
+/// The fraction of the phase error corrected on each external beat.
+const PHASE_GAIN: f64 = 0.5;
+
+/// The fraction of the tempo error corrected on each external beat.
+const TEMPO_GAIN: f32 = 0.2;
+
+/// Once the clock is following a source, beat intervals implying a tempo
+/// further than this fraction from the current tempo are taken to be missed
+/// or doubled beats, and ignored.
+const TEMPO_TOLERANCE: f32 = 0.25;
+
+/// How many consecutive beat intervals must agree on a tempo before the
+/// clock takes it from them, when a source starts or changes tempo.
+const LOCK_INTERVALS: usize = 2;
+
+/// A free-running beat clock.
+pub struct Clock {
+    bpm: f32,
+    /// The time of beat zero.
+    origin: Instant,
+    taps: Vec<Instant>,
+    last_beat: Option<Instant>,
+    /// Whether the tempo has been taken from the external beats.
+    locked: bool,
+    /// Tempos implied by recent beat intervals that agree with each other
+    /// but not with the current tempo.
+    pending: Vec<f32>,
+}
+
+impl Clock {
+    pub fn new() -> Self {
+        Self {
+            bpm: 120.,
+            origin: Instant::now(),
+            taps: Vec::new(),
+            last_beat: None,
+            locked: false,
+            pending: Vec::new(),
+        }
+    }
+
+    pub fn control(
+        &mut self,
+        msg: ClockControlMessage,
+        now: Instant,
+    ) -> Result<ClockStateChange, Box<dyn Error>> {
+        match msg {
+            ClockControlMessage::Tap => self.tap(now),
+            ClockControlMessage::SetBpm(bpm) => {
+                if !(MIN_BPM..=MAX_BPM).contains(&bpm) {
+                    bail!(
+                        "BPM must be between {} and {}, got {}",
+                        MIN_BPM,
+                        MAX_BPM,
+                        bpm
+                    );
+                }
+                self.set_bpm(bpm, now);
+            }
+            ClockControlMessage::Beat => self.beat(now),
+        }
+        Ok(ClockStateChange::Bpm(self.bpm))
+    }
+
+    fn tap(&mut self, now: Instant) {
+        if let Some(last) = self.taps.last() {
+            if now.saturating_duration_since(*last) > TAP_TIMEOUT {
+                self.taps.clear();
+            }
+        }
+        self.taps.push(now);
+        if self.taps.len() > MAX_TAPS {
+            self.taps.remove(0);
+        }
+        if let [first, .., last] = self.taps[..] {
+            let interval = (last - first).as_secs_f32() / (self.taps.len() - 1) as f32;
+            self.bpm = (60. / interval).clamp(MIN_BPM, MAX_BPM);
+        }
+        // The tap is a downbeat.
+        self.origin = now;
+    }
+
+    /// Follow an external beat. Jitter in when beats arrive is smoothed out
+    /// by correcting only part of the tempo and phase error on each beat.
+    fn beat(&mut self, now: Instant) {
+        let last = self.last_beat.replace(now);
+        let interval = match last.map(|last| now.saturating_duration_since(last)) {
+            Some(interval) if interval <= BEAT_TIMEOUT && !interval.is_zero() => interval,
+            _ => {
+                // A new source; snap to the nearest beat, and take the tempo
+                // from the intervals that follow.
+                self.locked = false;
+                self.pending.clear();
+                self.set_beats(self.beats(now).round(), now);
+                return;
+            }
+        };
+        let bpm = 60. / interval.as_secs_f32();
+        if self.locked && agrees(bpm, self.bpm) {
+            self.pending.clear();
+            let bpm = self.bpm + (bpm - self.bpm) * TEMPO_GAIN;
+            self.set_bpm(bpm.clamp(MIN_BPM, MAX_BPM), now);
+        } else {
+            // Either the source is new or it may have changed tempo. A single
+            // odd interval is a missed or doubled beat; enough agreeing ones
+            // are the new tempo.
+            if self.pending.last().is_some_and(|&last| !agrees(bpm, last)) {
+                self.pending.clear();
+            }
+            self.pending.push(bpm);
+            if self.pending.len() >= LOCK_INTERVALS {
+                let bpm = self.pending.iter().sum::<f32>() / self.pending.len() as f32;
+                self.pending.clear();
+                self.locked = true;
+                self.set_bpm(bpm.clamp(MIN_BPM, MAX_BPM), now);
+            }
+        }
+        let beats = self.beats(now);
+        let error = beats - beats.round();
+        self.set_beats(beats - error * PHASE_GAIN, now);
+    }
+
+    /// Change tempo without jumping the beat count.
+    fn set_bpm(&mut self, bpm: f32, now: Instant) {
+        let beats = self.beats(now);
+        self.bpm = bpm;
+        self.set_beats(beats, now);
+    }
+
+    /// Move beat zero so that the clock reads beats at now.
+    fn set_beats(&mut self, beats: f64, now: Instant) {
+        self.origin = now
+            .checked_sub(Duration::from_secs_f64(beats * 60. / self.bpm as f64))
+            .unwrap_or(now);
+    }
+
+    /// Return the number of beats since beat zero, including the fraction of
+    /// the current beat.
+    pub fn beats(&self, now: Instant) -> f64 {
+        now.saturating_duration_since(self.origin).as_secs_f64() * self.bpm as f64 / 60.
+    }
+
+    pub fn current_state(&self) -> ClockStateChange {
+        ClockStateChange::Bpm(self.bpm)
+    }
+}
+
+/// Whether a tempo is within the tolerance of another.
+fn agrees(bpm: f32, other: f32) -> bool {
+    ((bpm - other) / other).abs() <= TEMPO_TOLERANCE
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Feed the clock beats at a steady tempo, returning the time of the
+    /// last one.
+    fn beat_train(clock: &mut Clock, start: Instant, bpm: f32, count: u32) -> Instant {
+        let interval = Duration::from_secs_f32(60. / bpm);
+        let mut now = start;
+        for i in 0..count {
+            now = start + interval * i;
+            clock.control(ClockControlMessage::Beat, now).unwrap();
+        }
+        now
+    }
+
+    fn assert_locked(clock: &Clock, bpm: f32, now: Instant) {
+        assert!(
+            (clock.bpm - bpm).abs() < 0.5,
+            "expected {} BPM, got {}",
+            bpm,
+            clock.bpm
+        );
+        let beats = clock.beats(now);
+        assert!((beats - beats.round()).abs() < 0.05, "off beat: {}", beats);
+    }
+
+    #[test]
+    fn locks_to_slow_and_fast_sources() {
+        for bpm in [60., 174., MIN_BPM, MAX_BPM] {
+            let mut clock = Clock::new();
+            let now = beat_train(&mut clock, Instant::now(), bpm, 8);
+            assert_locked(&clock, bpm, now);
+        }
+    }
+
+    #[test]
+    fn follows_a_change_of_tempo() {
+        let mut clock = Clock::new();
+        let now = beat_train(&mut clock, Instant::now(), 120., 8);
+        let now = beat_train(&mut clock, now + Duration::from_secs_f32(60. / 80.), 80., 8);
+        assert_locked(&clock, 80., now);
+    }
+
+    #[test]
+    fn ignores_a_missed_beat() {
+        let mut clock = Clock::new();
+        let now = beat_train(&mut clock, Instant::now(), 128., 8);
+        let now = beat_train(
+            &mut clock,
+            now + Duration::from_secs_f32(120. / 128.),
+            128.,
+            4,
+        );
+        assert_locked(&clock, 128., now);
+    }
+}
@@ -1,56 +1,323 @@
+use std::cell::RefCell;
+use std::error::Error;
+use std::sync::mpsc::Sender;
+use std::time::Instant;
+
+use chrono::Local;
+
 use log::error;
-use shared::{ControlMessage, PaletteStateChange, StateChange, SubscriberStateChange};
+use shared::{
+    Color, ControlMessage, PaletteControlMessage, PaletteStateChange, StateChange,
+    SubscriberStateChange,
+};
 
 use crate::client::Clients;
+use crate::clock::Clock;
+use crate::effect::Effects;
+use crate::master::Master;
 use crate::osc::OscSender;
 use crate::palette::Palette;
+use crate::schedule::Scheduler;
+use crate::script::Scripts;
+use crate::sequencer::{Action, Sequencer, Tick};
+use crate::store::{SavedState, Store};
 use crate::subscriber::Subscribers;
 
+/// A control message, and where to send the state changes it causes if the
+/// sender is waiting for them.
+pub struct Request {
+    pub msg: ControlMessage,
+    pub reply: Option<Sender<Vec<StateChange>>>,
+    /// How many script reactions led to this message, or zero if it did not
+    /// come from a script.
+    pub depth: usize,
+}
+
+impl From<ControlMessage> for Request {
+    fn from(msg: ControlMessage) -> Self {
+        Self {
+            msg,
+            reply: None,
+            depth: 0,
+        }
+    }
+}
+
 pub struct Dispatcher {
     osc_sender: OscSender,
     palette: Palette,
+    master: Master,
+    sequencer: Sequencer,
+    clock: Clock,
+    effects: Effects,
+    scheduler: Scheduler,
+    scripts: Scripts,
+    store: Store,
     subs: Subscribers,
     clients: Clients,
+    /// True once the palette has refused a cue's colors, until it accepts
+    /// them again, so a locked palette is only reported once.
+    cue_refused: bool,
+    /// The script depth of the request being handled, passed on to scripts
+    /// reacting to the changes it causes.
+    depth: usize,
+    /// State changes caused by the request being handled, if it wants a
+    /// reply.
+    captured: RefCell<Option<Vec<StateChange>>>,
 }
 
 impl Dispatcher {
-    pub fn new(osc_sender: OscSender, palette: Palette, clients: Clients) -> Self {
+    pub fn new(
+        osc_sender: OscSender,
+        palette: Palette,
+        scheduler: Scheduler,
+        scripts: Scripts,
+        store: Store,
+        clients: Clients,
+    ) -> Self {
         Self {
             osc_sender,
             palette,
+            master: Master::new(),
+            sequencer: Sequencer::new(),
+            clock: Clock::new(),
+            effects: Effects::new(),
+            scheduler,
+            scripts,
+            store,
             subs: Subscribers::new(),
             clients,
+            cue_refused: false,
+            depth: 0,
+            captured: RefCell::new(None),
+        }
+    }
+
+    /// Handle a request, replying with the state changes it caused.
+    pub fn handle(&mut self, req: Request) {
+        self.depth = req.depth;
+        match req.reply {
+            Some(reply) => {
+                self.captured.replace(Some(Vec::new()));
+                self.control(req.msg);
+                let changes = self.captured.take().unwrap_or_default();
+                // The requester may have given up waiting.
+                let _ = reply.send(changes);
+            }
+            None => self.control(req.msg),
         }
+        // Changes made on ticks are not caused by scripts.
+        self.depth = 0;
     }
 
     pub fn control(&mut self, msg: ControlMessage) {
         use ControlMessage::*;
         match msg {
             Palette(m) => {
-                let control_result = self.palette.control(m);
-                if let PaletteStateChange::Set(ref colors) = control_result {
-                    self.subs.send_palette(&colors, &self.osc_sender);
+                let control_result = match self.palette.control(m) {
+                    Ok(sc) => sc,
+                    Err(e) => return self.report_error(e),
+                };
+                match control_result {
+                    // Exports leave the palette alone, and are only for
+                    // whoever asked for them.
+                    PaletteStateChange::Exported(_) => {
+                        return self.replay(StateChange::Palette(control_result))
+                    }
+                    PaletteStateChange::Saved(..) | PaletteStateChange::Deleted(_) => {
+                        self.persist()
+                    }
+                    // Report the next cue the palette refuses.
+                    PaletteStateChange::Locked(_) => self.cue_refused = false,
+                    _ => (),
                 }
+                self.send_output();
                 self.send_to_clients(StateChange::Palette(control_result))
             }
             Subscriber(m) => {
-                let control_result = self.subs.control(m);
-                if let SubscriberStateChange::Added(ref sub) = control_result {
-                    self.subs
-                        .send_palette_to(sub.id, self.palette.colors(), &self.osc_sender);
+                let control_result = match self.subs.control(m) {
+                    Ok(sc) => sc,
+                    Err(e) => return self.report_error(e),
+                };
+                // Bring new or reconfigured subscribers up to date.
+                match &control_result {
+                    SubscriberStateChange::Added(shared::Subscriber { id, .. })
+                    | SubscriberStateChange::CurveSet(id, _)
+                    | SubscriberStateChange::ChannelsSet(id, _)
+                    | SubscriberStateChange::EncodingSet(id, _) => {
+                        self.subs
+                            .send_palette_to(*id, &self.output(), &self.osc_sender);
+                    }
+                    SubscriberStateChange::Removed(_) => (),
                 }
                 self.send_to_clients(StateChange::Subscriber(control_result))
             }
+            Master(m) => {
+                let control_result = match self.master.control(m, &self.live()) {
+                    Ok(sc) => sc,
+                    Err(e) => return self.report_error(e),
+                };
+                self.send_output();
+                self.send_to_clients(StateChange::Master(control_result))
+            }
+            Cue(m) => match self.sequencer.control(m) {
+                Ok(Action::Changed(sc)) => self.send_to_clients(StateChange::Cue(sc)),
+                Ok(Action::Run(index)) => self.run_cue(index),
+                Err(e) => self.report_error(e),
+            },
+            Clock(m) => {
+                let control_result = match self.clock.control(m, Instant::now()) {
+                    Ok(sc) => sc,
+                    Err(e) => return self.report_error(e),
+                };
+                self.send_to_clients(StateChange::Clock(control_result))
+            }
+            Effect(m) => {
+                let control_result = match self.effects.control(m) {
+                    Ok(sc) => sc,
+                    Err(e) => return self.report_error(e),
+                };
+                self.send_output();
+                self.send_to_clients(StateChange::Effect(control_result))
+            }
+            Schedule(m) => {
+                let control_result = match self.scheduler.control(m) {
+                    Ok(sc) => sc,
+                    Err(e) => return self.report_error(e),
+                };
+                self.persist();
+                self.send_to_clients(StateChange::Schedule(control_result))
+            }
+            // Replayed state is not news, so scripts don't see it, and a
+            // requester waiting for a reply is the only one who needs it.
             Refresh => {
-                self.send_to_clients(StateChange::Palette(self.palette.current_state()));
+                for sc in self.palette.current_state() {
+                    self.replay(StateChange::Palette(sc));
+                }
                 for sc in self.subs.current_state() {
-                    self.send_to_clients(StateChange::Subscriber(sc));
+                    self.replay(StateChange::Subscriber(sc));
+                }
+                for sc in self.master.current_state() {
+                    self.replay(StateChange::Master(sc));
                 }
+                for sc in self.sequencer.current_state() {
+                    self.replay(StateChange::Cue(sc));
+                }
+                self.replay(StateChange::Clock(self.clock.current_state()));
+                for sc in self.effects.current_state() {
+                    self.replay(StateChange::Effect(sc));
+                }
+                for sc in self.scheduler.current_state() {
+                    self.replay(StateChange::Schedule(sc));
+                }
+                self.replay(StateChange::Clients(self.clients.current_state()));
+            }
+        };
+    }
+
+    /// Advance time-based behavior such as cue fades, follows and effects,
+    /// sending a frame to subscribers if the output is changing.
+    /// Call this once per frame, whether or not control messages have
+    /// arrived.
+    pub fn update(&mut self, now: Instant) {
+        self.scripts.reload(now);
+        for msg in self.scheduler.due(Local::now()) {
+            self.control(msg);
+        }
+        match self.sequencer.tick(now) {
+            Tick::Follow(index) => self.run_cue(index),
+            Tick::Fading => self.send_output(),
+            Tick::Idle if self.effects.is_active() || self.scripts.is_active() => {
+                self.send_output()
+            }
+            Tick::Idle => (),
+        }
+    }
+
+    /// Load a cue's palette and fade to it from the current look.
+    /// The cue runs even if the palette rejects its colors, so a locked
+    /// palette holds its look while the show carries on. Only the first
+    /// rejection is reported, rather than one for every cue.
+    fn run_cue(&mut self, index: usize) {
+        let from = self.sequencer.render(self.palette.colors());
+        let cue_state = self.sequencer.run(index, from);
+        let colors = self.sequencer.colors(index);
+        match self.palette.control(PaletteControlMessage::Set(colors)) {
+            Ok(sc) => {
+                self.cue_refused = false;
+                self.send_to_clients(StateChange::Palette(sc))
+            }
+            Err(e) if !self.cue_refused => {
+                self.cue_refused = true;
+                self.report_error(e)
             }
+            Err(_) => (),
+        }
+        self.send_output();
+        self.send_to_clients(StateChange::Cue(cue_state));
+    }
+
+    /// Return the look right now: the palette, blended through any cue
+    /// fade, with effects and scripts applied.
+    fn live(&self) -> Vec<Color> {
+        let now = Instant::now();
+        let beats = self.clock.beats(now);
+        let colors = self
+            .effects
+            .render(&self.sequencer.render(self.palette.colors()), now, beats);
+        self.scripts.render(&colors, now, beats)
+    }
+
+    /// Return the colors subscribers should receive: the live look with
+    /// master overrides applied.
+    fn output(&self) -> Vec<Color> {
+        self.master.render(&self.live())
+    }
+
+    /// Send the current output to all subscribers.
+    fn send_output(&self) {
+        self.subs.send_palette(&self.output(), &self.osc_sender);
+    }
+
+    /// Write saved palettes and the schedule to the store.
+    fn persist(&self) {
+        let state = SavedState {
+            palettes: self.palette.saved().clone(),
+            schedule: self.scheduler.saved(),
         };
+        if let Err(e) = self.store.save(&state) {
+            error!("Failed to save state: {}.", e);
+        }
+    }
+
+    /// Log a rejected control message and tell the requester waiting for a
+    /// reply about it, or otherwise all clients.
+    fn report_error(&self, e: Box<dyn Error>) {
+        error!("Control error: {}.", e);
+        let sc = StateChange::Error(e.to_string());
+        self.scripts.on_state(&sc, self.depth);
+        self.replay(sc);
     }
 
+    /// Let scripts react to a state change, and send it to clients.
     fn send_to_clients(&self, sc: StateChange) {
+        self.scripts.on_state(&sc, self.depth);
+        if let Some(captured) = self.captured.borrow_mut().as_mut() {
+            captured.push(sc.clone());
+        }
+        self.broadcast(sc);
+    }
+
+    /// Send current state to the requester waiting for a reply if there
+    /// is one, or otherwise to all clients.
+    fn replay(&self, sc: StateChange) {
+        match self.captured.borrow_mut().as_mut() {
+            Some(captured) => captured.push(sc),
+            None => self.broadcast(sc),
+        }
+    }
+
+    fn broadcast(&self, sc: StateChange) {
         if let Err(e) = self.clients.send_state_update(&sc) {
             error!(
                 "Failed to send state update to clients: {}.\nMissed update:\n{:?}",
@@ -0,0 +1,277 @@
+use std::error::Error;
+use std::f64::consts::TAU;
+use std::time::Instant;
+
+use shared::{
+    BeatEffect, BeatPattern, Color, Effect, EffectControlMessage, EffectId, EffectStateChange, Hsv,
+};
+use simple_error::bail;
+
+/// The fraction of a step that a strobe flash stays lit.
+const STROBE_DUTY: f64 = 0.25;
+
+/// The brightness a pulse decays to by the end of each step.
+const PULSE_FLOOR: f32 = 0.2;
+
+/// Motion applied to the palette on its way to subscribers.
+pub struct Effects {
+    /// Continuous effects, in the order they are applied.
+    effects: Vec<(EffectId, Effect)>,
+    next_id: EffectId,
+    beat: Option<BeatEffect>,
+    /// Continuous effects measure time from here.
+    epoch: Instant,
+}
+
+impl Effects {
+    pub fn new() -> Self {
+        Self {
+            effects: Vec::new(),
+            next_id: EffectId::ZERO,
+            beat: None,
+            epoch: Instant::now(),
+        }
+    }
+
+    pub fn control(
+        &mut self,
+        msg: EffectControlMessage,
+    ) -> Result<EffectStateChange, Box<dyn Error>> {
+        Ok(match msg {
+            EffectControlMessage::SetBeat(beat) => {
+                if let Some(effect) = beat {
+                    if !(effect.beats.is_finite() && effect.beats > 0.) {
+                        bail!("invalid effect step length {} beats", effect.beats);
+                    }
+                }
+                self.beat = beat;
+                EffectStateChange::Beat(beat)
+            }
+            EffectControlMessage::Add(effect) => {
+                validate(&effect)?;
+                let id = self.next_id;
+                self.next_id.advance();
+                self.effects.push((id, effect));
+                EffectStateChange::Added(id, effect)
+            }
+            EffectControlMessage::Remove(id) => {
+                let index = self.index(id)?;
+                self.effects.remove(index);
+                EffectStateChange::Removed(id)
+            }
+            EffectControlMessage::Update(id, effect) => {
+                validate(&effect)?;
+                let index = self.index(id)?;
+                self.effects[index].1 = effect;
+                EffectStateChange::Updated(id, effect)
+            }
+            EffectControlMessage::Clear => {
+                self.effects.clear();
+                EffectStateChange::Cleared
+            }
+        })
+    }
+
+    fn index(&self, id: EffectId) -> Result<usize, Box<dyn Error>> {
+        match self.effects.iter().position(|(i, _)| *i == id) {
+            Some(index) => Ok(index),
+            None => bail!("no effect found with ID {}", id),
+        }
+    }
+
+    /// True if the output changes over time, so frames must be sent
+    /// continuously.
+    pub fn is_active(&self) -> bool {
+        self.beat.is_some() || !self.effects.is_empty()
+    }
+
+    /// Apply effects to the palette at a point in time. Beat effects follow
+    /// the provided count of beats of the tempo clock.
+    pub fn render(&self, colors: &[Color], now: Instant, beats: f64) -> Vec<Color> {
+        let seconds = now.saturating_duration_since(self.epoch).as_secs_f64();
+        let mut colors = colors.to_vec();
+        for (_, effect) in &self.effects {
+            colors = render_effect(effect, &colors, seconds);
+        }
+        match self.beat {
+            Some(effect) => render_beat(effect, &colors, beats),
+            None => colors,
+        }
+    }
+
+    pub fn current_state(&self) -> impl Iterator<Item = EffectStateChange> + '_ {
+        self.effects
+            .iter()
+            .map(|(id, effect)| EffectStateChange::Added(*id, *effect))
+            .chain(Some(EffectStateChange::Beat(self.beat)))
+    }
+}
+
+fn validate(effect: &Effect) -> Result<(), Box<dyn Error>> {
+    let positive = |name: &str, v: f32| -> Result<(), Box<dyn Error>> {
+        if !(v.is_finite() && v > 0.) {
+            bail!("effect {} must be positive, got {}", name, v);
+        }
+        Ok(())
+    };
+    let unit = |name: &str, v: f32| -> Result<(), Box<dyn Error>> {
+        if !(0.0..=1.0).contains(&v) {
+            bail!("effect {} must be between 0 and 1, got {}", name, v);
+        }
+        Ok(())
+    };
+    match *effect {
+        Effect::Breathe { period, depth } => {
+            positive("period", period)?;
+            unit("depth", depth)
+        }
+        Effect::HueDrift { period } => positive("period", period),
+        Effect::Sparkle { density, decay } => {
+            positive("density", density)?;
+            positive("decay", decay)
+        }
+        Effect::Wave {
+            period,
+            wavelength,
+            depth,
+        } => {
+            positive("period", period)?;
+            positive("wavelength", wavelength)?;
+            unit("depth", depth)
+        }
+    }
+}
+
+fn render_effect(effect: &Effect, colors: &[Color], seconds: f64) -> Vec<Color> {
+    match *effect {
+        Effect::Breathe { period, depth } => {
+            let level = dip(depth, seconds / period as f64);
+            colors.iter().map(|c| c.scaled(level)).collect()
+        }
+        Effect::HueDrift { period } => {
+            let shift = (seconds / period as f64).fract() as f32;
+            colors
+                .iter()
+                .map(|c| {
+                    let mut hsv = Hsv::from(*c);
+                    hsv.hue = (hsv.hue + shift).fract();
+                    // Hue only concerns red, green and blue.
+                    Color {
+                        white: c.white,
+                        amber: c.amber,
+                        uv: c.uv,
+                        ..hsv.into()
+                    }
+                })
+                .collect()
+        }
+        Effect::Sparkle { density, decay } => colors
+            .iter()
+            .enumerate()
+            .map(|(i, c)| {
+                let k = sparkle(i, density as f64, decay as f64, seconds);
+                Color {
+                    red: c.red + (1. - c.red) * k,
+                    green: c.green + (1. - c.green) * k,
+                    blue: c.blue + (1. - c.blue) * k,
+                    ..*c
+                }
+            })
+            .collect(),
+        Effect::Wave {
+            period,
+            wavelength,
+            depth,
+        } => colors
+            .iter()
+            .enumerate()
+            .map(|(i, c)| {
+                let cycles = seconds / period as f64 - i as f64 / wavelength as f64;
+                c.scaled(dip(depth, cycles))
+            })
+            .collect(),
+    }
+}
+
+/// Return a brightness that starts each cycle at full and dips smoothly by
+/// depth halfway through.
+fn dip(depth: f32, cycles: f64) -> f32 {
+    1. - depth * (0.5 - 0.5 * (TAU * cycles).cos()) as f32
+}
+
+/// Return how brightly a swatch is sparkling, as a unit float.
+///
+/// Time is divided into slots as long as a sparkle's decay. Each swatch
+/// sparkles at most once per slot, at a pseudo-random offset, chosen so that
+/// the expected rate is density. Deriving sparkles from a hash keeps
+/// rendering free of state.
+fn sparkle(index: usize, density: f64, decay: f64, seconds: f64) -> f32 {
+    let chance = (density * decay).min(1.);
+    let slot = (seconds / decay).floor() as u64;
+    // A sparkle from the previous slot may still be fading.
+    [slot.saturating_sub(1), slot]
+        .into_iter()
+        .map(|slot| {
+            let h = hash(index as u64, slot);
+            if unit(h) >= chance {
+                return 0.;
+            }
+            let start = (slot as f64 + unit(h >> 32)) * decay;
+            let age = seconds - start;
+            if (0. ..decay).contains(&age) {
+                (1. - age / decay) as f32
+            } else {
+                0.
+            }
+        })
+        .fold(0., f32::max)
+}
+
+/// Mix two values into a well-distributed hash (SplitMix64).
+fn hash(a: u64, b: u64) -> u64 {
+    let mut z = a
+        .wrapping_mul(0x9e3779b97f4a7c15)
+        .wrapping_add(b)
+        .wrapping_add(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+/// Map the low 32 bits of a hash to a unit float.
+fn unit(h: u64) -> f64 {
+    (h & 0xffff_ffff) as f64 / (1u64 << 32) as f64
+}
+
+fn render_beat(effect: BeatEffect, colors: &[Color], beats: f64) -> Vec<Color> {
+    if colors.is_empty() {
+        return Vec::new();
+    }
+    let steps = beats / effect.beats as f64;
+    let step = steps.floor() as usize % colors.len();
+    let phase = steps.fract();
+    match effect.pattern {
+        BeatPattern::StepRotate => {
+            let mut colors = colors.to_vec();
+            colors.rotate_right(step);
+            colors
+        }
+        BeatPattern::Chase => colors
+            .iter()
+            .enumerate()
+            .map(|(i, c)| if i == step { *c } else { Color::BLACK })
+            .collect(),
+        BeatPattern::Strobe => {
+            let flash = if phase < STROBE_DUTY {
+                colors[step]
+            } else {
+                Color::BLACK
+            };
+            vec![flash; colors.len()]
+        }
+        BeatPattern::Pulse => {
+            let level = 1. - (1. - PULSE_FLOOR) * phase as f32;
+            colors.iter().map(|c| c.scaled(level)).collect()
+        }
+    }
+}
@@ -0,0 +1,201 @@
+use std::cmp::Reverse;
+use std::error::Error;
+use std::io::Cursor;
+
+use image::io::{Limits, Reader};
+use image::DynamicImage;
+use shared::{
+    Color, ControlMessage, Extraction, Hsv, Oklab, PaletteControlMessage, SortBy, MAX_PALETTE_SIZE,
+};
+use simple_error::bail;
+
+/// Images are shrunk to fit within this many pixels on a side before
+/// quantizing; dominant colors survive downsampling and it keeps
+/// extraction fast.
+const MAX_DIMENSION: u32 = 128;
+
+/// The largest image file accepted for extraction.
+const MAX_UPLOAD_SIZE: usize = 16 * 1024 * 1024;
+
+/// Images wider or taller than this are rejected before decoding.
+const MAX_IMAGE_DIMENSION: u32 = 8192;
+
+/// The most memory an image decoder may allocate.
+const MAX_DECODE_ALLOC: u64 = 256 * 1024 * 1024;
+
+/// Rounds of k-means used to refine the median cut.
+const REFINE_ITERATIONS: usize = 8;
+
+/// A representative color and the number of pixels it stands in for.
+struct Swatch {
+    color: Color,
+    population: usize,
+}
+
+/// Replace a request to extract a palette from an image with a request to
+/// set the extracted colors, so decoding happens on the requester's thread
+/// rather than holding up the dispatcher. Other messages are unchanged.
+pub fn resolve(msg: ControlMessage) -> Result<ControlMessage, Box<dyn Error>> {
+    Ok(match msg {
+        ControlMessage::Palette(PaletteControlMessage::Extract(upload)) => {
+            let colors = extract(&upload.image, &upload.extraction)?;
+            ControlMessage::Palette(PaletteControlMessage::Set(colors))
+        }
+        msg => msg,
+    })
+}
+
+/// Decode an encoded image file and return its dominant colors.
+pub fn extract(image: &[u8], extraction: &Extraction) -> Result<Vec<Color>, Box<dyn Error>> {
+    if extraction.count == 0 {
+        bail!("cannot extract an empty palette");
+    }
+    if extraction.count > MAX_PALETTE_SIZE {
+        bail!(
+            "cannot extract {} colors; the most is {}",
+            extraction.count,
+            MAX_PALETTE_SIZE
+        );
+    }
+    if image.len() > MAX_UPLOAD_SIZE {
+        bail!(
+            "image is {} bytes; the most is {}",
+            image.len(),
+            MAX_UPLOAD_SIZE
+        );
+    }
+    let pixels: Vec<[u8; 3]> = decode(image)?
+        .thumbnail(MAX_DIMENSION, MAX_DIMENSION)
+        .to_rgb8()
+        .pixels()
+        .map(|p| p.0)
+        .collect();
+    if pixels.is_empty() {
+        bail!("image has no pixels");
+    }
+    let centroids = median_cut(pixels.clone(), extraction.count);
+    let mut swatches = refine(&pixels, centroids);
+    sort(&mut swatches, extraction.sort);
+    Ok(swatches.into_iter().map(|s| s.color).collect())
+}
+
+/// Decode an image, refusing any too large to decode quickly.
+fn decode(image: &[u8]) -> Result<DynamicImage, Box<dyn Error>> {
+    let mut reader = Reader::new(Cursor::new(image)).with_guessed_format()?;
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAX_IMAGE_DIMENSION);
+    limits.max_image_height = Some(MAX_IMAGE_DIMENSION);
+    limits.max_alloc = Some(MAX_DECODE_ALLOC);
+    reader.limits(limits);
+    Ok(reader.decode()?)
+}
+
+/// Quantize pixels by repeatedly splitting the box with the widest channel
+/// range at its median, until there are count boxes or no box can be split.
+/// Returns the average color of each box.
+fn median_cut(pixels: Vec<[u8; 3]>, count: usize) -> Vec<[f32; 3]> {
+    let mut boxes = vec![pixels];
+    while boxes.len() < count {
+        let (index, channel, range) = boxes
+            .iter()
+            .enumerate()
+            .map(|(i, pixels)| {
+                let (channel, range) = widest_channel(pixels);
+                (i, channel, range)
+            })
+            .max_by_key(|(_, _, range)| *range)
+            .unwrap();
+        if range == 0 {
+            // Every box holds a single color.
+            break;
+        }
+        let mut lower = boxes.swap_remove(index);
+        lower.sort_unstable_by_key(|p| p[channel]);
+        let upper = lower.split_off(lower.len() / 2);
+        boxes.push(lower);
+        boxes.push(upper);
+    }
+    boxes.iter().map(|pixels| average(pixels)).collect()
+}
+
+/// Median cut splits clusters that straddle a median; move the centroids to
+/// the clusters with a few rounds of k-means.
+fn refine(pixels: &[[u8; 3]], mut centroids: Vec<[f32; 3]>) -> Vec<Swatch> {
+    let mut clusters: Vec<Vec<[u8; 3]>> = Vec::new();
+    for _ in 0..REFINE_ITERATIONS {
+        clusters = vec![Vec::new(); centroids.len()];
+        for p in pixels {
+            clusters[nearest(&centroids, p)].push(*p);
+        }
+        clusters.retain(|c| !c.is_empty());
+        let updated: Vec<[f32; 3]> = clusters.iter().map(|c| average(c)).collect();
+        if updated == centroids {
+            break;
+        }
+        centroids = updated;
+    }
+    clusters
+        .iter()
+        .map(|pixels| {
+            let [r, g, b] = average(pixels);
+            Swatch {
+                color: Color::rgb(r / 255., g / 255., b / 255.),
+                population: pixels.len(),
+            }
+        })
+        .collect()
+}
+
+fn nearest(centroids: &[[f32; 3]], pixel: &[u8; 3]) -> usize {
+    let distance = |c: &[f32; 3]| -> f32 {
+        c.iter()
+            .zip(pixel)
+            .map(|(c, p)| (c - *p as f32).powi(2))
+            .sum()
+    };
+    (0..centroids.len())
+        .min_by(|a, b| distance(&centroids[*a]).total_cmp(&distance(&centroids[*b])))
+        .unwrap()
+}
+
+/// Return the channel with the largest range of values, and that range.
+fn widest_channel(pixels: &[[u8; 3]]) -> (usize, u8) {
+    (0..3)
+        .map(|channel| {
+            let values = pixels.iter().map(|p| p[channel]);
+            let range = values.clone().max().unwrap_or(0) - values.min().unwrap_or(0);
+            (channel, range)
+        })
+        .max_by_key(|(_, range)| *range)
+        .unwrap()
+}
+
+/// Return the mean of the pixels, in 8-bit units.
+fn average(pixels: &[[u8; 3]]) -> [f32; 3] {
+    let mut sums = [0u64; 3];
+    for p in pixels {
+        for (sum, v) in sums.iter_mut().zip(p) {
+            *sum += *v as u64;
+        }
+    }
+    sums.map(|sum| sum as f32 / pixels.len() as f32)
+}
+
+fn sort(swatches: &mut [Swatch], sort: SortBy) {
+    match sort {
+        SortBy::Population => swatches.sort_by_key(|s| Reverse(s.population)),
+        SortBy::Hue => swatches.sort_by(|a, b| hsv(a).hue.total_cmp(&hsv(b).hue)),
+        SortBy::Lightness => swatches.sort_by(|a, b| {
+            Oklab::from(a.color)
+                .lightness
+                .total_cmp(&Oklab::from(b.color).lightness)
+        }),
+        SortBy::Saturation => {
+            swatches.sort_by(|a, b| hsv(b).saturation.total_cmp(&hsv(a).saturation))
+        }
+    }
+}
+
+fn hsv(swatch: &Swatch) -> Hsv {
+    swatch.color.into()
+}
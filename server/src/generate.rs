@@ -0,0 +1,107 @@
+use std::error::Error;
+
+use shared::{Color, Harmony, HarmonyRule, Hsl, Hsv, MAX_PALETTE_SIZE};
+use simple_error::bail;
+
+/// Analogous colors are spaced 30 degrees apart.
+const ANALOGOUS_STEP: f32 = 1. / 12.;
+
+/// Produce a palette from the provided harmony.
+/// The base color is always the first color. If more colors are requested
+/// than the rule has hues, the hues repeat at successively lower values.
+pub fn generate(harmony: &Harmony) -> Result<Vec<Color>, Box<dyn Error>> {
+    let Harmony { base, rule, count } = *harmony;
+    if count == 0 {
+        bail!("cannot generate an empty palette");
+    }
+    if count > MAX_PALETTE_SIZE {
+        bail!(
+            "cannot generate {} colors; the most is {}",
+            count,
+            MAX_PALETTE_SIZE
+        );
+    }
+    base.validate()?;
+    if rule == HarmonyRule::Monochromatic {
+        return Ok(monochromatic(base, count));
+    }
+    let offsets = hue_offsets(rule, count);
+    let tiers = count.div_ceil(offsets.len());
+    let base = Hsv::from(base);
+    Ok((0..count)
+        .map(|i| {
+            let tier = (i / offsets.len()) as f32;
+            Hsv {
+                hue: (base.hue + offsets[i % offsets.len()]).rem_euclid(1.),
+                saturation: base.saturation,
+                value: base.value * (1. - 0.6 * tier / tiers as f32),
+            }
+            .into()
+        })
+        .collect())
+}
+
+/// Return the hue offsets for a rule, as fractions of a turn.
+fn hue_offsets(rule: HarmonyRule, count: usize) -> Vec<f32> {
+    use HarmonyRule::*;
+    match rule {
+        Complementary => vec![0., 0.5],
+        Triadic => vec![0., 1. / 3., 2. / 3.],
+        // Walk outward from the base in alternating directions.
+        Analogous => (0..count)
+            .map(|i| {
+                let step = i.div_ceil(2) as f32 * ANALOGOUS_STEP;
+                if i % 2 == 0 {
+                    -step
+                } else {
+                    step
+                }
+            })
+            .collect(),
+        SplitComplementary => vec![0., 5. / 12., 7. / 12.],
+        Tetradic => vec![0., 0.25, 0.5, 0.75],
+        Monochromatic => vec![0.],
+    }
+}
+
+/// Ramp lightness around the base color, keeping its hue and saturation.
+/// The base color is first and unchanged, followed by progressively darker
+/// and lighter variations, which are kept away from black and white.
+fn monochromatic(base: Color, count: usize) -> Vec<Color> {
+    let hsl = Hsl::from(base);
+    (0..count)
+        .map(|i| {
+            if i == 0 {
+                return base;
+            }
+            let step = i.div_ceil(2) as f32 / count as f32;
+            let offset = if i % 2 == 0 { step } else { -step };
+            Hsl {
+                lightness: (hsl.lightness + offset).clamp(0.05, 0.95),
+                ..hsl
+            }
+            .into()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn base_color_comes_first_unchanged() {
+        for rule in [HarmonyRule::Monochromatic, HarmonyRule::Triadic] {
+            for base in [Color::rgb(1., 1., 1.), Color::rgb(0.01, 0., 0.02)] {
+                let colors = generate(&Harmony {
+                    base,
+                    rule,
+                    count: 5,
+                })
+                .unwrap();
+                assert_eq!(colors.len(), 5);
+                assert_eq!(colors[0], base, "{:?}", rule);
+            }
+        }
+    }
+}
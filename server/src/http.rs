@@ -0,0 +1,268 @@
+use std::error::Error;
+use std::io::Read;
+use std::net::SocketAddr;
+use std::sync::mpsc::{channel, Sender};
+use std::thread;
+use std::time::Duration;
+
+use log::{error, info};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use shared::{
+    Color, ControlMessage, PaletteControlMessage, PaletteStateChange, StateChange,
+    SubscriberConfig, SubscriberControlMessage, SubscriberId, SubscriberStateChange,
+};
+use tiny_http::{Header, Method, Response, Server};
+
+use crate::auth::{query_param, Auth, Role};
+use crate::control::Request;
+use crate::extract;
+use crate::tls::Tls;
+
+/// How long to wait for the dispatcher to handle a request.
+const REPLY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The largest request body accepted, with room for an image upload
+/// written out as a JSON array of bytes.
+const MAX_BODY: usize = 64 * 1024 * 1024;
+
+/// Serve a JSON API over HTTP for integrations that would rather not hold a
+/// websocket open. Bodies use the same types as websocket clients.
+///
+/// - `GET /state`: the full current state, as sent to a refreshing client.
+/// - `POST /control`: handle any control message, returning the state
+///   changes it caused.
+/// - `GET /palette`, `PUT /palette`: get or set the palette colors.
+/// - `GET /subscribers`, `POST /subscribers`: list subscribers, or add one
+///   from its config.
+/// - `DELETE /subscribers/<id>`: remove a subscriber, or 404 if there is
+///   none with that ID.
+///
+/// A rejected control message gets a 400 response with the error as its
+/// body.
+///
+/// Clients present a token as a bearer token in the Authorization header,
+/// or as `?token=<token>` in the URL.
+pub fn serve(
+    addr: SocketAddr,
+    auth: Auth,
+    tls: Option<Tls>,
+    send: Sender<Request>,
+) -> Result<(), Box<dyn Error>> {
+    let server = match &tls {
+        Some(tls) => Server::https(addr, tls.ssl_config()),
+        None => Server::http(addr),
+    }
+    .map_err(|e| e.to_string())?;
+    info!(
+        "Serving HTTP API on {}{}.",
+        addr,
+        if tls.is_some() { " using TLS" } else { "" }
+    );
+    thread::spawn(move || {
+        for mut req in server.incoming_requests() {
+            let send = send.clone();
+            let auth = auth.clone();
+            // Requests wait on the dispatcher, so don't let one hold up the
+            // rest.
+            thread::spawn(move || {
+                // Check the token before reading the body, so clients without
+                // one cannot make the server buffer large uploads.
+                let reply = match auth.role(token(&req)) {
+                    Some(role) => read_body(&mut req)
+                        .and_then(|body| route(req.method(), req.url(), &body, role, &send)),
+                    None => Err(Failure::Unauthorized),
+                };
+                let (status, body) = match reply {
+                    Ok(reply) => reply,
+                    Err(failure) => failure.into_reply(),
+                };
+                let response = Response::from_string(body)
+                    .with_status_code(status)
+                    .with_header(Header::from_bytes("Content-Type", "application/json").unwrap());
+                if let Err(e) = req.respond(response) {
+                    error!("Failed to send HTTP response: {}.", e);
+                }
+            });
+        }
+    });
+    Ok(())
+}
+
+/// An HTTP status and JSON body.
+type Reply = (u16, String);
+
+enum Failure {
+    BadRequest(String),
+    /// No valid token was presented.
+    Unauthorized,
+    /// The client's role does not allow the request.
+    Forbidden,
+    NotFound,
+    MethodNotAllowed,
+    /// The request body is larger than MAX_BODY.
+    TooLarge,
+    /// The dispatcher did not reply in time.
+    Unavailable,
+}
+
+impl Failure {
+    fn into_reply(self) -> Reply {
+        let (status, message) = match self {
+            Failure::BadRequest(e) => (400, e),
+            Failure::Unauthorized => (401, "a valid token is required".to_string()),
+            Failure::Forbidden => (403, "observers cannot make changes".to_string()),
+            Failure::NotFound => (404, "not found".to_string()),
+            Failure::MethodNotAllowed => (405, "method not allowed".to_string()),
+            Failure::TooLarge => (
+                413,
+                format!("request bodies may be at most {} bytes", MAX_BODY),
+            ),
+            Failure::Unavailable => (503, "the server did not respond".to_string()),
+        };
+        // Serializing a string cannot fail.
+        (status, serde_json::to_string(&message).unwrap())
+    }
+}
+
+fn route(
+    method: &Method,
+    url: &str,
+    body: &str,
+    role: Role,
+    send: &Sender<Request>,
+) -> Result<Reply, Failure> {
+    let path = url.split('?').next().unwrap_or_default();
+    let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+    match (method, segments.as_slice()) {
+        (Method::Get, ["state"]) => ok(&request(role, send, ControlMessage::Refresh)?),
+        (Method::Post, ["control"]) => ok(&request(role, send, parse(body)?)?),
+        (Method::Get, ["palette"]) => ok(&palette(request(role, send, ControlMessage::Refresh)?)),
+        (Method::Put, ["palette"]) => {
+            let colors: Vec<Color> = parse(body)?;
+            let changes = request(
+                role,
+                send,
+                ControlMessage::Palette(PaletteControlMessage::Set(colors)),
+            )?;
+            ok(&palette(changes))
+        }
+        (Method::Get, ["subscribers"]) => {
+            let subscribers: Vec<_> = request(role, send, ControlMessage::Refresh)?
+                .into_iter()
+                .filter_map(|sc| match sc {
+                    StateChange::Subscriber(SubscriberStateChange::Added(sub)) => Some(sub),
+                    _ => None,
+                })
+                .collect();
+            ok(&subscribers)
+        }
+        (Method::Post, ["subscribers"]) => {
+            let cfg: SubscriberConfig = parse(body)?;
+            let changes = request(
+                role,
+                send,
+                ControlMessage::Subscriber(SubscriberControlMessage::Add(cfg)),
+            )?;
+            let added = changes.into_iter().find_map(|sc| match sc {
+                StateChange::Subscriber(SubscriberStateChange::Added(sub)) => Some(sub),
+                _ => None,
+            });
+            Ok((201, to_json(&added)))
+        }
+        (Method::Delete, ["subscribers", id]) => {
+            let id: SubscriberId = parse(id)?;
+            request(
+                role,
+                send,
+                ControlMessage::Subscriber(SubscriberControlMessage::Remove(id)),
+            )
+            .map_err(|e| match e {
+                // Removal only fails for unknown subscribers.
+                Failure::BadRequest(_) => Failure::NotFound,
+                e => e,
+            })?;
+            Ok((204, String::new()))
+        }
+        (_, ["state" | "control" | "palette" | "subscribers"] | ["subscribers", _]) => {
+            Err(Failure::MethodNotAllowed)
+        }
+        _ => Err(Failure::NotFound),
+    }
+}
+
+/// Have the dispatcher handle a control message, returning the state
+/// changes it caused.
+fn request(
+    role: Role,
+    send: &Sender<Request>,
+    msg: ControlMessage,
+) -> Result<Vec<StateChange>, Failure> {
+    if !role.allows(&msg) {
+        return Err(Failure::Forbidden);
+    }
+    let msg = extract::resolve(msg).map_err(|e| Failure::BadRequest(e.to_string()))?;
+    let (reply, recv) = channel();
+    send.send(Request {
+        msg,
+        reply: Some(reply),
+        depth: 0,
+    })
+    .map_err(|_| Failure::Unavailable)?;
+    let changes = recv
+        .recv_timeout(REPLY_TIMEOUT)
+        .map_err(|_| Failure::Unavailable)?;
+    for sc in &changes {
+        if let StateChange::Error(e) = sc {
+            return Err(Failure::BadRequest(e.clone()));
+        }
+    }
+    Ok(changes)
+}
+
+/// Return the palette colors among some state changes.
+fn palette(changes: Vec<StateChange>) -> Option<Vec<Color>> {
+    changes.into_iter().find_map(|sc| match sc {
+        StateChange::Palette(PaletteStateChange::Set(colors))
+        | StateChange::Palette(PaletteStateChange::SetGradient(_, colors)) => Some(colors),
+        _ => None,
+    })
+}
+
+fn parse<T: DeserializeOwned>(body: &str) -> Result<T, Failure> {
+    serde_json::from_str(body).map_err(|e| Failure::BadRequest(e.to_string()))
+}
+
+fn ok<T: Serialize>(value: &T) -> Result<Reply, Failure> {
+    Ok((200, to_json(value)))
+}
+
+fn to_json<T: Serialize>(value: &T) -> String {
+    // Our state types always serialize.
+    serde_json::to_string(value).unwrap()
+}
+
+/// Read a request body as text, refusing any larger than MAX_BODY.
+fn read_body(req: &mut tiny_http::Request) -> Result<String, Failure> {
+    if req.body_length().is_some_and(|len| len > MAX_BODY) {
+        return Err(Failure::TooLarge);
+    }
+    let mut body = Vec::new();
+    req.as_reader()
+        .take(MAX_BODY as u64 + 1)
+        .read_to_end(&mut body)
+        .map_err(|e| Failure::BadRequest(e.to_string()))?;
+    if body.len() > MAX_BODY {
+        return Err(Failure::TooLarge);
+    }
+    String::from_utf8(body).map_err(|e| Failure::BadRequest(e.to_string()))
+}
+
+/// Return the token presented with a request, if any.
+fn token(req: &tiny_http::Request) -> Option<&str> {
+    req.headers()
+        .iter()
+        .find(|h| h.field.equiv("Authorization"))
+        .and_then(|h| h.value.as_str().strip_prefix("Bearer "))
+        .or_else(|| query_param(req.url(), "token"))
+}
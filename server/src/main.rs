@@ -1,74 +1,266 @@
+use std::fs;
 use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
-use std::thread;
-use std::time::Duration;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::RecvTimeoutError;
+use std::time::{Duration, Instant};
 use std::{error::Error, sync::mpsc::channel};
 
+use auth::Auth;
+use clap::{Args, Parser, Subcommand};
 use client::Clients;
 use control::Dispatcher;
 use osc::OscSender;
 use palette::Palette;
+use schedule::Scheduler;
+use script::Scripts;
 use shared::{
-    Color, ControlMessage, PaletteControlMessage, SubscriberConfig, SubscriberControlMessage,
+    Color, ControlMessage, Cue, CueControlMessage, Extraction, PaletteFile, PaletteFormat, SortBy,
+    SubscriberConfig, SubscriberControlMessage,
 };
 use simple_error::bail;
+use store::Store;
+use tls::Tls;
 
+mod assets;
+mod auth;
 mod client;
+mod clock;
 mod control;
+mod effect;
+mod extract;
+mod generate;
+mod http;
+mod master;
 mod osc;
 mod palette;
+mod randomize;
+mod schedule;
+mod script;
+mod sequencer;
+mod store;
 mod subscriber;
+mod tempo;
+mod tls;
+
+#[derive(Parser)]
+#[clap(about = "Serve color palettes to subscribers and control clients.")]
+struct Cli {
+    #[clap(subcommand)]
+    command: Option<Command>,
+    #[clap(flatten)]
+    server: ServerOptions,
+}
+
+#[derive(Args)]
+struct ServerOptions {
+    /// Accept websocket clients, and serve the controller app, on this
+    /// address.
+    #[clap(long, default_value = "127.0.0.1:8081")]
+    addr: SocketAddr,
+    /// Serve the controller app from this directory, such as the output of
+    /// `trunk build`.
+    #[clap(long)]
+    assets: Option<PathBuf>,
+    /// Require clients to present this token to connect with full control.
+    #[clap(long)]
+    token: Option<String>,
+    /// Let clients presenting this token connect as observers, which
+    /// receive state without making changes.
+    #[clap(long, requires = "token")]
+    view_token: Option<String>,
+    /// Serve websocket clients, the controller app and the HTTP API over
+    /// TLS with this PEM certificate chain.
+    #[clap(long, requires = "key")]
+    cert: Option<PathBuf>,
+    /// The PEM private key for the TLS certificate.
+    #[clap(long, requires = "cert")]
+    key: Option<PathBuf>,
+    /// Follow /beat and /bpm OSC messages received on this port.
+    #[clap(long)]
+    tempo_port: Option<u16>,
+    /// Frames per second sent to subscribers while fades or effects run,
+    /// from 1 to 1000.
+    #[clap(long, default_value_t = 40.)]
+    fps: f64,
+    /// Keep saved palettes and the schedule in this JSON file.
+    #[clap(long)]
+    state: Option<PathBuf>,
+    /// Run the Rhai scripts in this directory, reloading them as they
+    /// change.
+    #[clap(long)]
+    scripts: Option<PathBuf>,
+    /// Serve the HTTP API on this address, such as 127.0.0.1:8080.
+    #[clap(long)]
+    http: Option<SocketAddr>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Print the dominant colors of a PNG or JPEG image as hex strings.
+    Extract {
+        image: PathBuf,
+        /// The number of colors to extract.
+        #[clap(short, long, default_value_t = 5)]
+        count: usize,
+        /// Order colors by population, hue, lightness or saturation.
+        #[clap(short, long, default_value_t = SortBy::Population)]
+        sort: SortBy,
+    },
+    /// Convert a palette file between formats: gpl, ase, css, hex or json.
+    /// Formats are inferred from file extensions unless provided.
+    Convert {
+        input: PathBuf,
+        output: PathBuf,
+        #[clap(long)]
+        from: Option<PaletteFormat>,
+        #[clap(long)]
+        to: Option<PaletteFormat>,
+    },
+}
 
 fn main() -> Result<(), Box<dyn Error>> {
+    let cli = Cli::parse();
+    match cli.command {
+        None => run_server(cli.server),
+        Some(Command::Extract { image, count, sort }) => {
+            let colors = extract::extract(&fs::read(image)?, &Extraction { count, sort })?;
+            for color in colors {
+                println!("{}", color.to_hex());
+            }
+            Ok(())
+        }
+        Some(Command::Convert {
+            input,
+            output,
+            from,
+            to,
+        }) => {
+            let from = format_for(&input, from)?;
+            let to = format_for(&output, to)?;
+            let colors = palette::format::import(&PaletteFile {
+                format: from,
+                data: fs::read(input)?,
+            })?;
+            fs::write(output, palette::format::export(to, &colors)?.data)?;
+            Ok(())
+        }
+    }
+}
+
+/// Use the provided format, or infer it from the file extension.
+fn format_for(path: &Path, format: Option<PaletteFormat>) -> Result<PaletteFormat, Box<dyn Error>> {
+    if let Some(format) = format {
+        return Ok(format);
+    }
+    match path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .and_then(PaletteFormat::from_extension)
+    {
+        Some(format) => Ok(format),
+        None => bail!("cannot infer palette format of {}", path.display()),
+    }
+}
+
+/// The range of frame rates the output loop can run at.
+const MIN_FPS: f64 = 1.;
+const MAX_FPS: f64 = 1000.;
+
+fn run_server(opts: ServerOptions) -> Result<(), Box<dyn Error>> {
+    if !(MIN_FPS..=MAX_FPS).contains(&opts.fps) {
+        bail!(
+            "frame rate must be between {} and {}, got {}",
+            MIN_FPS,
+            MAX_FPS,
+            opts.fps
+        );
+    }
+    let frame = Duration::from_secs_f64(1. / opts.fps);
     simple_logger::SimpleLogger::new().init().unwrap();
     let (send, recv) = channel();
 
     let dest_addr = SocketAddrV4::new(Ipv4Addr::new(127, 0, 0, 1), 11000);
 
-    send.send(ControlMessage::Subscriber(SubscriberControlMessage::Add(
-        SubscriberConfig::Osc(SocketAddr::V4(dest_addr)),
-    )))?;
-
-    let clients = Clients::new(send.clone())?;
-
-    let mut dispatcher = Dispatcher::new(OscSender::new(10000)?, Palette::new(), clients);
-
-    // Test - periodically send a palette update.
-    thread::spawn(move || {
-        let mut colors = vec![
-            Color {
-                red: 1.,
-                green: 1.,
-                blue: 0.,
-            },
-            Color {
-                red: 1.,
-                green: 0.,
-                blue: 1.,
-            },
-            Color {
-                red: 0.,
-                green: 1.,
-                blue: 1.,
-            },
-        ];
-        loop {
-            if let Err(_) = send.send(ControlMessage::Palette(PaletteControlMessage::Set(
-                colors.clone(),
-            ))) {
-                break;
-            }
-            thread::sleep(Duration::from_secs(5));
+    send.send(
+        ControlMessage::Subscriber(SubscriberControlMessage::Add(SubscriberConfig::Osc(
+            SocketAddr::V4(dest_addr),
+        )))
+        .into(),
+    )?;
+
+    let auth = Auth::new(opts.token, opts.view_token);
+    let tls = match (&opts.cert, &opts.key) {
+        (Some(cert), Some(key)) => Some(Tls::load(cert, key)?),
+        _ => None,
+    };
+    let clients = Clients::new(
+        opts.addr,
+        opts.assets,
+        auth.clone(),
+        tls.clone(),
+        send.clone(),
+    )?;
+
+    if let Some(port) = opts.tempo_port {
+        tempo::listen(port, send.clone())?;
+    }
+
+    if let Some(addr) = opts.http {
+        http::serve(addr, auth, tls, send.clone())?;
+    }
+
+    let store = Store::new(opts.state);
+    let saved = store.load()?;
+    let mut dispatcher = Dispatcher::new(
+        OscSender::new(10000)?,
+        Palette::new(saved.palettes),
+        Scheduler::new(saved.schedule)?,
+        Scripts::new(opts.scripts, send.clone()),
+        store,
+        clients,
+    );
+
+    // Test - loop through rotations of a palette.
+    let mut colors = vec![
+        Color::rgb(1., 1., 0.),
+        Color::rgb(1., 0., 1.),
+        Color::rgb(0., 1., 1.),
+    ];
+    let cues = (0..colors.len())
+        .map(|i| {
+            let cue = Cue {
+                name: format!("Rotation {}", i),
+                colors: colors.clone(),
+                fade: 1.,
+                hold: 4.,
+                follow: true,
+            };
             colors.rotate_right(1);
-        }
-    });
+            cue
+        })
+        .collect();
+    send.send(ControlMessage::Cue(CueControlMessage::SetCues(cues)).into())?;
+    send.send(ControlMessage::Cue(CueControlMessage::Go).into())?;
 
+    // Handle control messages as they arrive, rendering a frame on schedule
+    // in between.
+    let mut next_frame = Instant::now();
     loop {
-        let msg = match recv.recv() {
-            Ok(msg) => msg,
-            Err(_) => {
+        match recv.recv_timeout(next_frame.saturating_duration_since(Instant::now())) {
+            Ok(req) => dispatcher.handle(req),
+            Err(RecvTimeoutError::Timeout) => (),
+            Err(RecvTimeoutError::Disconnected) => {
                 bail!("Control message channel disconnected; exiting.");
             }
-        };
-        dispatcher.control(msg);
+        }
+        let now = Instant::now();
+        if now >= next_frame {
+            dispatcher.update(now);
+            next_frame += frame;
+            // If we fell behind, drop frames rather than bursting to catch up.
+            if next_frame <= now {
+                next_frame = now + frame;
+            }
+        }
     }
 }
@@ -0,0 +1,72 @@
+use std::error::Error;
+
+use shared::{Color, MasterControlMessage, MasterStateChange};
+use simple_error::bail;
+
+/// Global output overrides, applied on top of the palette before sending
+/// colors to subscribers.
+pub struct Master {
+    level: f32,
+    blackout: bool,
+    /// If frozen, the palette that was live when the freeze was engaged.
+    frozen: Option<Vec<Color>>,
+}
+
+impl Master {
+    pub fn new() -> Self {
+        Self {
+            level: 1.0,
+            blackout: false,
+            frozen: None,
+        }
+    }
+
+    /// Handle a control message.
+    /// The live palette is needed to capture the held output on freeze.
+    pub fn control(
+        &mut self,
+        msg: MasterControlMessage,
+        live: &[Color],
+    ) -> Result<MasterStateChange, Box<dyn Error>> {
+        Ok(match msg {
+            MasterControlMessage::Level(level) => {
+                if !(0.0..=1.0).contains(&level) {
+                    bail!("invalid master level {}", level);
+                }
+                self.level = level;
+                MasterStateChange::Level(level)
+            }
+            MasterControlMessage::Blackout(blackout) => {
+                self.blackout = blackout;
+                MasterStateChange::Blackout(blackout)
+            }
+            MasterControlMessage::Freeze(freeze) => {
+                if !freeze {
+                    self.frozen = None;
+                } else if self.frozen.is_none() {
+                    self.frozen = Some(live.to_vec());
+                }
+                MasterStateChange::Freeze(freeze)
+            }
+        })
+    }
+
+    pub fn current_state(&self) -> impl Iterator<Item = MasterStateChange> {
+        [
+            MasterStateChange::Level(self.level),
+            MasterStateChange::Blackout(self.blackout),
+            MasterStateChange::Freeze(self.frozen.is_some()),
+        ]
+        .into_iter()
+    }
+
+    /// Produce the colors that should be sent to subscribers given the live
+    /// palette. A frozen palette still responds to level and blackout.
+    pub fn render(&self, live: &[Color]) -> Vec<Color> {
+        let colors = self.frozen.as_deref().unwrap_or(live);
+        if self.blackout {
+            return vec![Color::BLACK; colors.len()];
+        }
+        colors.iter().map(|c| c.scaled(self.level)).collect()
+    }
+}
@@ -1,27 +1,223 @@
-use shared::{Color, PaletteControlMessage, PaletteStateChange};
+use std::collections::BTreeMap;
+use std::error::Error;
 
-pub struct Palette(Vec<Color>);
+use shared::{Color, Gradient, PaletteControlMessage, PaletteStateChange, MAX_PALETTE_SIZE};
+use simple_error::bail;
+
+use crate::extract::extract;
+use crate::generate::generate;
+use crate::randomize::randomize;
+
+pub mod format;
+
+pub struct Palette {
+    colors: Vec<Color>,
+    /// If the palette was sampled from a gradient, keep it for re-editing.
+    gradient: Option<Gradient>,
+    /// Lock flags for each swatch, always as long as colors.
+    swatch_locks: Vec<bool>,
+    /// While locked, every edit to the palette is rejected.
+    locked: bool,
+    /// Palettes saved by name for recall.
+    saved: BTreeMap<String, Vec<Color>>,
+}
 
 impl Palette {
-    pub fn new() -> Self {
-        Self(Vec::new())
+    pub fn new(saved: BTreeMap<String, Vec<Color>>) -> Self {
+        Self {
+            colors: Vec::new(),
+            gradient: None,
+            swatch_locks: Vec::new(),
+            locked: false,
+            saved,
+        }
+    }
+
+    pub fn saved(&self) -> &BTreeMap<String, Vec<Color>> {
+        &self.saved
     }
 
     pub fn colors(&self) -> &[Color] {
-        &self.0
+        &self.colors
     }
 
-    pub fn control(&mut self, msg: PaletteControlMessage) -> PaletteStateChange {
+    /// Handle a control message.
+    /// Messages containing invalid colors are rejected without changing the
+    /// palette, as are all edits while the palette is locked.
+    pub fn control(
+        &mut self,
+        msg: PaletteControlMessage,
+    ) -> Result<PaletteStateChange, Box<dyn Error>> {
+        let edit = !matches!(
+            msg,
+            PaletteControlMessage::Export(_)
+                | PaletteControlMessage::LockSwatch(..)
+                | PaletteControlMessage::Lock(_)
+                | PaletteControlMessage::Save(_)
+                | PaletteControlMessage::Delete(_)
+        );
+        if edit && self.locked {
+            bail!("palette is locked");
+        }
         match msg {
-            PaletteControlMessage::Set(colors) => {
-                self.0.clear();
-                self.0.extend_from_slice(&colors);
-                PaletteStateChange::Set(colors)
+            PaletteControlMessage::Set(colors) => self.set(colors),
+            PaletteControlMessage::SetEntries(entries) => {
+                self.set(entries.into_iter().map(Color::from).collect())
+            }
+            PaletteControlMessage::Generate(harmony) => self.set(generate(&harmony)?),
+            // Clients' uploads arrive already extracted, by extract::resolve.
+            PaletteControlMessage::Extract(upload) => {
+                self.set(extract(&upload.image, &upload.extraction)?)
+            }
+            PaletteControlMessage::Randomize(r) => {
+                let locked = self.locked_colors();
+                self.set(randomize(&r, &locked)?)
+            }
+            PaletteControlMessage::Import(file) => self.set(format::import(&file)?),
+            PaletteControlMessage::Export(format) => Ok(PaletteStateChange::Exported(
+                format::export(format, &self.colors)?,
+            )),
+            PaletteControlMessage::SetGradient(gradient) => {
+                validate_gradient(&gradient)?;
+                let colors = gradient.colors();
+                validate(&colors)?;
+                self.replace(colors);
+                self.gradient = Some(gradient.clone());
+                Ok(PaletteStateChange::SetGradient(
+                    gradient,
+                    self.colors.clone(),
+                ))
+            }
+            PaletteControlMessage::LockSwatch(index, locked) => {
+                match self.swatch_locks.get_mut(index) {
+                    Some(lock) => *lock = locked,
+                    None => bail!(
+                        "cannot lock swatch {} of a {} color palette",
+                        index,
+                        self.colors.len()
+                    ),
+                }
+                Ok(PaletteStateChange::SwatchLocks(self.swatch_locks.clone()))
+            }
+            PaletteControlMessage::Lock(locked) => {
+                self.locked = locked;
+                Ok(PaletteStateChange::Locked(locked))
+            }
+            PaletteControlMessage::Save(name) => {
+                if name.is_empty() {
+                    bail!("saved palettes need a name");
+                }
+                self.saved.insert(name.clone(), self.colors.clone());
+                Ok(PaletteStateChange::Saved(name, self.colors.clone()))
+            }
+            PaletteControlMessage::Recall(name) => match self.saved.get(&name) {
+                Some(colors) => self.set(colors.clone()),
+                None => bail!("no palette saved as \"{}\"", name),
+            },
+            PaletteControlMessage::Delete(name) => {
+                if self.saved.remove(&name).is_none() {
+                    bail!("no palette saved as \"{}\"", name);
+                }
+                Ok(PaletteStateChange::Deleted(name))
             }
         }
     }
 
-    pub fn current_state(&self) -> PaletteStateChange {
-        PaletteStateChange::Set(self.0.clone())
+    fn set(&mut self, colors: Vec<Color>) -> Result<PaletteStateChange, Box<dyn Error>> {
+        validate(&colors)?;
+        self.replace(colors);
+        self.gradient = None;
+        Ok(PaletteStateChange::Set(self.colors.clone()))
+    }
+
+    /// Return the colors of locked swatches.
+    fn locked_colors(&self) -> Vec<Color> {
+        self.colors
+            .iter()
+            .zip(&self.swatch_locks)
+            .filter(|(_, locked)| **locked)
+            .map(|(color, _)| *color)
+            .collect()
+    }
+
+    /// Replace the colors, leaving locked swatches untouched.
+    /// If a locked swatch lies beyond the end of the new colors, the palette
+    /// keeps its existing colors up to and including that swatch.
+    fn replace(&mut self, colors: Vec<Color>) {
+        let len = self
+            .swatch_locks
+            .iter()
+            .rposition(|locked| *locked)
+            .map_or(0, |i| i + 1)
+            .max(colors.len());
+        self.colors = (0..len)
+            .map(|i| match colors.get(i) {
+                Some(color) if !self.swatch_locks.get(i).copied().unwrap_or(false) => *color,
+                _ => self.colors[i],
+            })
+            .collect();
+        self.swatch_locks.resize(len, false);
+    }
+
+    pub fn current_state(&self) -> Vec<PaletteStateChange> {
+        let colors = match self.gradient {
+            Some(ref gradient) => {
+                PaletteStateChange::SetGradient(gradient.clone(), self.colors.clone())
+            }
+            None => PaletteStateChange::Set(self.colors.clone()),
+        };
+        let mut states = vec![
+            colors,
+            PaletteStateChange::SwatchLocks(self.swatch_locks.clone()),
+            PaletteStateChange::Locked(self.locked),
+        ];
+        states.extend(
+            self.saved
+                .iter()
+                .map(|(name, colors)| PaletteStateChange::Saved(name.clone(), colors.clone())),
+        );
+        states
+    }
+}
+
+/// Ensure every color is valid, identifying the first bad color by index.
+fn validate(colors: &[Color]) -> Result<(), Box<dyn Error>> {
+    if colors.len() > MAX_PALETTE_SIZE {
+        bail!(
+            "palette has {} colors; the most is {}",
+            colors.len(),
+            MAX_PALETTE_SIZE
+        );
+    }
+    for (i, color) in colors.iter().enumerate() {
+        color
+            .validate()
+            .map_err(|e| format!("palette color {}: {}", i, e))?;
+    }
+    Ok(())
+}
+
+fn validate_gradient(gradient: &Gradient) -> Result<(), Box<dyn Error>> {
+    if gradient.stops.is_empty() {
+        bail!("gradient has no stops");
+    }
+    if gradient.count == 0 {
+        bail!("cannot sample an empty palette from a gradient");
+    }
+    if gradient.count > MAX_PALETTE_SIZE {
+        bail!(
+            "cannot sample {} colors from a gradient; the most is {}",
+            gradient.count,
+            MAX_PALETTE_SIZE
+        );
+    }
+    for (i, stop) in gradient.stops.iter().enumerate() {
+        if !(0.0..=1.0).contains(&stop.position) {
+            bail!("gradient stop {}: invalid position {}", i, stop.position);
+        }
+        stop.color
+            .validate()
+            .map_err(|e| format!("gradient stop {}: {}", i, e))?;
     }
+    Ok(())
 }
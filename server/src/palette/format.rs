@@ -0,0 +1,275 @@
+use std::error::Error;
+
+use shared::{Color, PaletteFile, PaletteFormat};
+use simple_error::bail;
+
+/// Decode the colors in a palette file.
+pub fn import(file: &PaletteFile) -> Result<Vec<Color>, Box<dyn Error>> {
+    let colors = match file.format {
+        PaletteFormat::Ase => import_ase(&file.data)?,
+        PaletteFormat::Json => serde_json::from_slice(&file.data)?,
+        format => {
+            let text = std::str::from_utf8(&file.data)?;
+            match format {
+                PaletteFormat::Gpl => import_gpl(text)?,
+                PaletteFormat::Css => import_css(text)?,
+                _ => import_hex(text)?,
+            }
+        }
+    };
+    if colors.is_empty() {
+        bail!("no colors found in {} palette", file.format);
+    }
+    Ok(colors)
+}
+
+/// Encode colors as a palette file.
+pub fn export(format: PaletteFormat, colors: &[Color]) -> Result<PaletteFile, Box<dyn Error>> {
+    let data = match format {
+        PaletteFormat::Gpl => export_gpl(colors).into_bytes(),
+        PaletteFormat::Ase => export_ase(colors),
+        PaletteFormat::Css => export_css(colors).into_bytes(),
+        PaletteFormat::Hex => export_hex(colors).into_bytes(),
+        PaletteFormat::Json => serde_json::to_vec_pretty(colors)?,
+    };
+    Ok(PaletteFile { format, data })
+}
+
+fn import_gpl(text: &str) -> Result<Vec<Color>, Box<dyn Error>> {
+    let mut lines = text.lines();
+    if lines.next().map(str::trim) != Some("GIMP Palette") {
+        bail!("missing GIMP Palette header");
+    }
+    let mut colors = Vec::new();
+    for line in lines.map(str::trim) {
+        if line.is_empty()
+            || line.starts_with('#')
+            || line.starts_with("Name:")
+            || line.starts_with("Columns:")
+        {
+            continue;
+        }
+        // Anything after the three components is the swatch name.
+        let components = line
+            .split_whitespace()
+            .take(3)
+            .map(str::parse)
+            .collect::<Result<Vec<u8>, _>>()
+            .map_err(|e| format!("invalid GIMP palette line \"{}\": {}", line, e))?;
+        if components.len() != 3 {
+            bail!("invalid GIMP palette line \"{}\"", line);
+        }
+        colors.push(Color::from_u8(components[0], components[1], components[2]));
+    }
+    Ok(colors)
+}
+
+fn export_gpl(colors: &[Color]) -> String {
+    let mut out = String::from("GIMP Palette\nName: palette_control\nColumns: 0\n#\n");
+    for (i, color) in colors.iter().enumerate() {
+        let (r, g, b) = color.as_u8();
+        out.push_str(&format!("{:3} {:3} {:3}\tColor {}\n", r, g, b, i));
+    }
+    out
+}
+
+/// Read the values of every custom property that holds a hex color.
+fn import_css(text: &str) -> Result<Vec<Color>, Box<dyn Error>> {
+    let mut colors = Vec::new();
+    for declaration in text.split([';', '{', '}']) {
+        let (name, value) = match declaration.split_once(':') {
+            Some((name, value)) => (name.trim(), value.trim()),
+            None => continue,
+        };
+        if name.starts_with("--") && value.starts_with('#') {
+            colors.push(Color::from_hex(value)?);
+        }
+    }
+    Ok(colors)
+}
+
+fn export_css(colors: &[Color]) -> String {
+    let mut out = String::from(":root {\n");
+    for (i, color) in colors.iter().enumerate() {
+        out.push_str(&format!("  --palette-{}: {};\n", i, color.to_hex()));
+    }
+    out.push_str("}\n");
+    out
+}
+
+/// Hex colors may be separated by whitespace or commas.
+fn import_hex(text: &str) -> Result<Vec<Color>, Box<dyn Error>> {
+    Ok(text
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|s| !s.is_empty())
+        .map(Color::from_hex)
+        .collect::<Result<_, _>>()?)
+}
+
+fn export_hex(colors: &[Color]) -> String {
+    colors.iter().map(|c| c.to_hex() + "\n").collect()
+}
+
+const ASE_SIGNATURE: &[u8] = b"ASEF";
+const ASE_COLOR_ENTRY: u16 = 0x0001;
+/// Swatches in a palette are "normal" rather than global or spot colors.
+const ASE_NORMAL_COLOR: u16 = 2;
+
+fn import_ase(data: &[u8]) -> Result<Vec<Color>, Box<dyn Error>> {
+    let mut r = Reader(data);
+    if r.take(4)? != ASE_SIGNATURE {
+        bail!("missing ASEF signature");
+    }
+    r.take(4)?; // version
+    let block_count = r.u32()?;
+    let mut colors = Vec::new();
+    for _ in 0..block_count {
+        let block_type = r.u16()?;
+        let len = r.u32()? as usize;
+        let mut block = Reader(r.take(len)?);
+        // Skip group start and end blocks.
+        if block_type != ASE_COLOR_ENTRY {
+            continue;
+        }
+        let name_len = block.u16()? as usize;
+        block.take(name_len * 2)?;
+        let model = block.take(4)?;
+        let color = match model {
+            b"RGB " => Color::rgb(block.f32()?, block.f32()?, block.f32()?),
+            b"Gray" => {
+                let v = block.f32()?;
+                Color::rgb(v, v, v)
+            }
+            b"CMYK" => {
+                let (c, m, y, k) = (block.f32()?, block.f32()?, block.f32()?, block.f32()?);
+                Color::rgb(
+                    (1. - c) * (1. - k),
+                    (1. - m) * (1. - k),
+                    (1. - y) * (1. - k),
+                )
+            }
+            other => bail!(
+                "unsupported ASE color model \"{}\"",
+                String::from_utf8_lossy(other)
+            ),
+        };
+        colors.push(color);
+    }
+    Ok(colors)
+}
+
+fn export_ase(colors: &[Color]) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.extend_from_slice(ASE_SIGNATURE);
+    out.extend_from_slice(&1u16.to_be_bytes());
+    out.extend_from_slice(&0u16.to_be_bytes());
+    out.extend_from_slice(&(colors.len() as u32).to_be_bytes());
+    for (i, color) in colors.iter().enumerate() {
+        // Names are null-terminated UTF-16.
+        let name: Vec<u16> = format!("Color {}", i)
+            .encode_utf16()
+            .chain(Some(0))
+            .collect();
+        let mut block = Vec::new();
+        block.extend_from_slice(&(name.len() as u16).to_be_bytes());
+        for unit in name {
+            block.extend_from_slice(&unit.to_be_bytes());
+        }
+        block.extend_from_slice(b"RGB ");
+        for v in [color.red, color.green, color.blue] {
+            block.extend_from_slice(&v.to_be_bytes());
+        }
+        block.extend_from_slice(&ASE_NORMAL_COLOR.to_be_bytes());
+
+        out.extend_from_slice(&ASE_COLOR_ENTRY.to_be_bytes());
+        out.extend_from_slice(&(block.len() as u32).to_be_bytes());
+        out.extend_from_slice(&block);
+    }
+    out
+}
+
+/// Read big-endian values from the front of a byte slice.
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], Box<dyn Error>> {
+        if self.0.len() < n {
+            bail!("unexpected end of ASE data");
+        }
+        let (head, tail) = self.0.split_at(n);
+        self.0 = tail;
+        Ok(head)
+    }
+
+    fn u16(&mut self) -> Result<u16, Box<dyn Error>> {
+        Ok(u16::from_be_bytes(self.take(2)?.try_into()?))
+    }
+
+    fn u32(&mut self) -> Result<u32, Box<dyn Error>> {
+        Ok(u32::from_be_bytes(self.take(4)?.try_into()?))
+    }
+
+    fn f32(&mut self) -> Result<f32, Box<dyn Error>> {
+        Ok(f32::from_be_bytes(self.take(4)?.try_into()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Colors every format can represent exactly: 8-bit RGB.
+    fn palette() -> Vec<Color> {
+        vec![
+            Color::from_u8(0, 0, 0),
+            Color::from_u8(255, 255, 255),
+            Color::from_u8(255, 128, 0),
+            Color::from_u8(1, 2, 3),
+            Color::from_u8(18, 52, 86),
+        ]
+    }
+
+    fn round_trip(format: PaletteFormat, colors: &[Color]) -> Vec<Color> {
+        import(&export(format, colors).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn gpl_round_trip() {
+        assert_eq!(round_trip(PaletteFormat::Gpl, &palette()), palette());
+    }
+
+    #[test]
+    fn ase_round_trip() {
+        assert_eq!(round_trip(PaletteFormat::Ase, &palette()), palette());
+        // ASE stores floats, so needn't round to 8 bits.
+        let precise = vec![Color::rgb(0.1, 0.2, 0.3)];
+        assert_eq!(round_trip(PaletteFormat::Ase, &precise), precise);
+    }
+
+    #[test]
+    fn css_round_trip() {
+        assert_eq!(round_trip(PaletteFormat::Css, &palette()), palette());
+    }
+
+    #[test]
+    fn hex_round_trip() {
+        assert_eq!(round_trip(PaletteFormat::Hex, &palette()), palette());
+    }
+
+    #[test]
+    fn json_round_trip() {
+        assert_eq!(round_trip(PaletteFormat::Json, &palette()), palette());
+        let precise = vec![Color {
+            white: Some(0.25),
+            ..Color::rgb(0.1, 0.2, 0.3)
+        }];
+        assert_eq!(round_trip(PaletteFormat::Json, &precise), precise);
+    }
+
+    #[test]
+    fn empty_palettes_are_rejected() {
+        for format in PaletteFormat::ALL {
+            assert!(import(&export(format, &[]).unwrap()).is_err());
+        }
+    }
+}
@@ -0,0 +1,124 @@
+use std::error::Error;
+use std::ops::RangeInclusive;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use shared::{Color, Hsl, Oklab, Randomization, MAX_PALETTE_SIZE};
+use simple_error::bail;
+
+/// Candidates drawn per requested color before giving up on spacing them.
+const ATTEMPTS_PER_COLOR: usize = 1000;
+
+/// The most candidates drawn for one palette, which keeps a large palette
+/// from holding up the dispatcher.
+const MAX_ATTEMPTS: usize = 20_000;
+
+/// Draw random colors within the constraints, rejecting any candidate that
+/// is perceptually too close to a color already chosen or to one of the
+/// colors being kept.
+pub fn randomize(r: &Randomization, kept: &[Color]) -> Result<Vec<Color>, Box<dyn Error>> {
+    if r.count == 0 {
+        bail!("cannot generate an empty palette");
+    }
+    if r.count > MAX_PALETTE_SIZE {
+        bail!(
+            "cannot generate {} colors; the most is {}",
+            r.count,
+            MAX_PALETTE_SIZE
+        );
+    }
+    check_range("saturation", &r.saturation)?;
+    check_range("lightness", &r.lightness)?;
+    if r.min_distance.is_nan() || r.min_distance < 0. {
+        bail!(
+            "minimum distance must be non-negative, got {}",
+            r.min_distance
+        );
+    }
+    let mut rng = match r.seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
+    let attempts = r.count.saturating_mul(ATTEMPTS_PER_COLOR).min(MAX_ATTEMPTS);
+    let mut chosen: Vec<Oklab> = kept.iter().map(|c| Oklab::from(*c)).collect();
+    let wanted = kept.len() + r.count;
+    for _ in 0..attempts {
+        if chosen.len() == wanted {
+            break;
+        }
+        let candidate = Oklab::from(Color::from(Hsl {
+            hue: rng.gen_range(0. ..1.),
+            saturation: rng.gen_range(r.saturation.clone()),
+            lightness: rng.gen_range(r.lightness.clone()),
+        }));
+        if chosen
+            .iter()
+            .all(|c| distance(c, &candidate) >= r.min_distance)
+        {
+            chosen.push(candidate);
+        }
+    }
+    if chosen.len() < wanted {
+        bail!(
+            "could only find {} of {} colors at least {} apart; lower the minimum distance or widen the ranges",
+            chosen.len() - kept.len(),
+            r.count,
+            r.min_distance
+        );
+    }
+    Ok(chosen
+        .into_iter()
+        .skip(kept.len())
+        .map(Color::from)
+        .collect())
+}
+
+fn check_range(name: &str, range: &RangeInclusive<f32>) -> Result<(), Box<dyn Error>> {
+    let (start, end) = (*range.start(), *range.end());
+    if !(0. ..=1.).contains(&start) || !(0. ..=1.).contains(&end) || start > end {
+        bail!(
+            "{} range must be an increasing pair of unit floats, got {}..={}",
+            name,
+            start,
+            end
+        );
+    }
+    Ok(())
+}
+
+fn distance(a: &Oklab, b: &Oklab) -> f32 {
+    ((a.lightness - b.lightness).powi(2) + (a.a - b.a).powi(2) + (a.b - b.b).powi(2)).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn randomization(count: usize) -> Randomization {
+        Randomization {
+            count,
+            saturation: 0.5..=1.,
+            lightness: 0.35..=0.65,
+            min_distance: 0.1,
+            seed: Some(7),
+        }
+    }
+
+    #[test]
+    fn colors_are_spaced_from_kept_colors() {
+        let kept = [Color::rgb(1., 0., 0.), Color::rgb(0., 0.5, 1.)];
+        let colors = randomize(&randomization(4), &kept).unwrap();
+        assert_eq!(colors.len(), 4);
+        for color in colors {
+            for k in kept {
+                assert!(distance(&color.into(), &k.into()) >= 0.1 - 1e-4);
+            }
+        }
+    }
+
+    #[test]
+    fn impossible_spacing_gives_up() {
+        let e = randomize(&randomization(MAX_PALETTE_SIZE), &[]).unwrap_err();
+        assert!(e.to_string().contains("could only find"), "{}", e);
+    }
+}
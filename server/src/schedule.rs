@@ -0,0 +1,171 @@
+use std::error::Error;
+use std::str::FromStr;
+
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone};
+use shared::{
+    ControlMessage, ScheduleControlMessage, ScheduleId, ScheduleStateChange, Scheduled, Trigger,
+};
+use simple_error::bail;
+
+/// Formats accepted for one-off trigger times.
+const AT_FORMATS: [&str; 2] = ["%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M"];
+
+/// Sends control messages at scheduled wall-clock times.
+pub struct Scheduler {
+    entries: Vec<Entry>,
+    next_id: ScheduleId,
+}
+
+struct Entry {
+    id: ScheduleId,
+    scheduled: Scheduled,
+    times: Times,
+    /// When this entry next fires, if ever.
+    next: Option<DateTime<Local>>,
+}
+
+enum Times {
+    At(DateTime<Local>),
+    Cron(Box<cron::Schedule>),
+}
+
+impl Times {
+    fn parse(trigger: &Trigger) -> Result<Self, Box<dyn Error>> {
+        Ok(match trigger {
+            Trigger::At(s) => {
+                let naive = AT_FORMATS
+                    .iter()
+                    .find_map(|format| NaiveDateTime::parse_from_str(s.trim(), format).ok());
+                let time = match naive.map(|naive| Local.from_local_datetime(&naive).earliest()) {
+                    Some(Some(time)) => time,
+                    Some(None) => bail!("\"{}\" does not exist in local time", s),
+                    None => bail!("invalid time \"{}\"; expected YYYY-MM-DD HH:MM[:SS]", s),
+                };
+                Times::At(time)
+            }
+            Trigger::Cron(s) => Times::Cron(Box::new(
+                cron::Schedule::from_str(s)
+                    .map_err(|e| format!("invalid cron expression \"{}\": {}", s, e))?,
+            )),
+        })
+    }
+
+    /// Return the first time strictly after the provided time.
+    fn after(&self, time: DateTime<Local>) -> Option<DateTime<Local>> {
+        match self {
+            Times::At(at) => (*at > time).then_some(*at),
+            Times::Cron(schedule) => schedule.after(&time).next(),
+        }
+    }
+}
+
+impl Entry {
+    fn new(
+        id: ScheduleId,
+        scheduled: Scheduled,
+        now: DateTime<Local>,
+    ) -> Result<Self, Box<dyn Error>> {
+        let times = Times::parse(&scheduled.trigger)?;
+        let next = times.after(now);
+        Ok(Self {
+            id,
+            scheduled,
+            times,
+            next,
+        })
+    }
+
+    fn check_upcoming(&self) -> Result<(), Box<dyn Error>> {
+        if self.next.is_none() {
+            bail!("the scheduled time has already passed");
+        }
+        Ok(())
+    }
+}
+
+impl Scheduler {
+    /// Restore a saved schedule. One-off actions whose time has passed are
+    /// kept but never fire.
+    pub fn new(saved: Vec<(ScheduleId, Scheduled)>) -> Result<Self, Box<dyn Error>> {
+        let now = Local::now();
+        let next_id = match saved.iter().map(|(id, _)| *id).max() {
+            Some(mut id) => {
+                id.advance();
+                id
+            }
+            None => ScheduleId::ZERO,
+        };
+        let entries = saved
+            .into_iter()
+            .map(|(id, scheduled)| Entry::new(id, scheduled, now))
+            .collect::<Result<_, _>>()?;
+        Ok(Self { entries, next_id })
+    }
+
+    pub fn control(
+        &mut self,
+        msg: ScheduleControlMessage,
+    ) -> Result<ScheduleStateChange, Box<dyn Error>> {
+        let now = Local::now();
+        Ok(match msg {
+            ScheduleControlMessage::Add(scheduled) => {
+                let id = self.next_id;
+                let entry = Entry::new(id, scheduled.clone(), now)?;
+                entry.check_upcoming()?;
+                self.next_id.advance();
+                self.entries.push(entry);
+                ScheduleStateChange::Added(id, scheduled)
+            }
+            ScheduleControlMessage::Remove(id) => {
+                let index = self.index(id)?;
+                self.entries.remove(index);
+                ScheduleStateChange::Removed(id)
+            }
+            ScheduleControlMessage::Update(id, scheduled) => {
+                let index = self.index(id)?;
+                let entry = Entry::new(id, scheduled.clone(), now)?;
+                entry.check_upcoming()?;
+                self.entries[index] = entry;
+                ScheduleStateChange::Updated(id, scheduled)
+            }
+        })
+    }
+
+    fn index(&self, id: ScheduleId) -> Result<usize, Box<dyn Error>> {
+        match self.entries.iter().position(|e| e.id == id) {
+            Some(index) => Ok(index),
+            None => bail!("no scheduled action found with ID {}", id),
+        }
+    }
+
+    /// Return the actions that have come due, in schedule order, and
+    /// schedule their next firing.
+    pub fn due(&mut self, now: DateTime<Local>) -> Vec<ControlMessage> {
+        let mut due = Vec::new();
+        for entry in &mut self.entries {
+            match entry.next {
+                Some(next) if next <= now => (),
+                _ => continue,
+            }
+            entry.next = entry.times.after(now);
+            if entry.scheduled.enabled {
+                due.push((*entry.scheduled.action).clone());
+            }
+        }
+        due
+    }
+
+    /// Return the schedule for saving.
+    pub fn saved(&self) -> Vec<(ScheduleId, Scheduled)> {
+        self.entries
+            .iter()
+            .map(|e| (e.id, e.scheduled.clone()))
+            .collect()
+    }
+
+    pub fn current_state(&self) -> impl Iterator<Item = ScheduleStateChange> + '_ {
+        self.entries
+            .iter()
+            .map(|e| ScheduleStateChange::Added(e.id, e.scheduled.clone()))
+    }
+}
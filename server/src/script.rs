@@ -0,0 +1,321 @@
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Sender;
+use std::time::{Duration, Instant, SystemTime};
+
+use log::{error, info};
+use rhai::serde::{from_dynamic, to_dynamic};
+use rhai::{CallFnOptions, Dynamic, Engine, FuncArgs, Map, Scope, AST};
+use shared::{Color, ControlMessage, StateChange};
+
+use crate::control::Request;
+
+/// How often to check the script directory for changes.
+const RELOAD_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The most operations a single script call may run, so a runaway script
+/// cannot stall the server.
+const MAX_OPERATIONS: u64 = 1_000_000;
+
+/// The longest chain of scripts reacting to state changes caused by
+/// scripts. A script that would go further is taken to be reacting to its
+/// own changes without end, and is disabled.
+const MAX_DEPTH: usize = 8;
+
+/// Rhai scripts loaded from the *.rhai files in a directory, and reloaded
+/// when those files change. Scripts run in order of file name.
+///
+/// A script may define either or both of:
+/// - `render(colors, context)`, which returns the colors to output given the
+///   live colors and a context map holding `time` in seconds and `beats` of
+///   the tempo clock. Colors may be returned in any form a palette accepts,
+///   such as RGB maps or hex strings.
+/// - `on_state(change)`, which reacts to a state change sent to clients by
+///   returning a control message, an array of them, or nothing.
+///
+/// In both, `this` is a map that keeps its contents between calls. A script
+/// that fails to load or run is disabled until it is edited, as is one that
+/// keeps reacting to changes caused by scripts.
+/// Without a directory, no scripts run.
+pub struct Scripts {
+    dir: Option<PathBuf>,
+    engine: Engine,
+    scripts: BTreeMap<PathBuf, Script>,
+    /// Control messages issued by scripts go here.
+    send: Sender<Request>,
+    last_scan: Option<Instant>,
+    /// Render time is measured from here.
+    epoch: Instant,
+}
+
+struct Script {
+    modified: SystemTime,
+    ast: AST,
+    /// The value bound to `this`.
+    state: RefCell<Dynamic>,
+    disabled: Cell<bool>,
+}
+
+impl Scripts {
+    pub fn new(dir: Option<PathBuf>, send: Sender<Request>) -> Self {
+        let mut engine = Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS);
+        Self {
+            dir,
+            engine,
+            scripts: BTreeMap::new(),
+            send,
+            last_scan: None,
+            epoch: Instant::now(),
+        }
+    }
+
+    /// Load new and edited scripts and drop deleted ones. The directory is
+    /// checked at most once per reload interval, so call this as often as
+    /// convenient.
+    pub fn reload(&mut self, now: Instant) {
+        let dir = match &self.dir {
+            Some(dir) => dir,
+            None => return,
+        };
+        if let Some(last) = self.last_scan {
+            if now.saturating_duration_since(last) < RELOAD_INTERVAL {
+                return;
+            }
+        }
+        self.last_scan = Some(now);
+        let found = match scan(dir) {
+            Ok(found) => found,
+            Err(e) => {
+                error!("Failed to read script directory {}: {}.", dir.display(), e);
+                return;
+            }
+        };
+        self.scripts.retain(|path, _| {
+            let keep = found.contains_key(path);
+            if !keep {
+                info!("Unloaded script {}.", path.display());
+            }
+            keep
+        });
+        for (path, modified) in found {
+            if let Some(script) = self.scripts.get(&path) {
+                if script.modified == modified {
+                    continue;
+                }
+            }
+            let script = match self.compile(&path) {
+                Ok(ast) => {
+                    info!("Loaded script {}.", path.display());
+                    Script::new(modified, ast)
+                }
+                Err(e) => {
+                    error!("Failed to load script {}: {}.", path.display(), e);
+                    let script = Script::new(modified, AST::empty());
+                    script.disabled.set(true);
+                    script
+                }
+            };
+            self.scripts.insert(path, script);
+        }
+    }
+
+    fn compile(&self, path: &Path) -> Result<AST, Box<dyn Error>> {
+        Ok(self.engine.compile(fs::read_to_string(path)?)?)
+    }
+
+    /// Return the enabled scripts that define a function.
+    fn defining<'a>(&'a self, name: &'a str) -> impl Iterator<Item = (&'a Path, &'a Script)> + 'a {
+        self.scripts
+            .iter()
+            .filter(move |(_, script)| !script.disabled.get() && script.defines(name))
+            .map(|(path, script)| (path.as_path(), script))
+    }
+
+    /// True if a script renders colors, so frames must be sent
+    /// continuously.
+    pub fn is_active(&self) -> bool {
+        self.defining("render").next().is_some()
+    }
+
+    /// Pass colors through each script that renders them.
+    pub fn render(&self, colors: &[Color], now: Instant, beats: f64) -> Vec<Color> {
+        let mut colors = colors.to_vec();
+        let mut context = Map::new();
+        context.insert(
+            "time".into(),
+            now.saturating_duration_since(self.epoch)
+                .as_secs_f64()
+                .into(),
+        );
+        context.insert("beats".into(), beats.into());
+        for (path, script) in self.defining("render") {
+            match script.render(&self.engine, &colors, context.clone()) {
+                Ok(rendered) => colors = rendered,
+                Err(e) => script.disable(path, "render", e),
+            }
+        }
+        colors
+    }
+
+    /// Let scripts react to a state change, queueing the control messages
+    /// they return. Depth is how many script reactions led to the change.
+    pub fn on_state(&self, sc: &StateChange, depth: usize) {
+        let mut handlers = self.defining("on_state").peekable();
+        if handlers.peek().is_none() {
+            return;
+        }
+        let change = match to_dynamic(sc) {
+            Ok(change) => change,
+            Err(e) => {
+                error!("Failed to pass state change to scripts: {}.", e);
+                return;
+            }
+        };
+        for (path, script) in handlers {
+            match script.on_state(&self.engine, change.clone()) {
+                Ok(msgs) if !msgs.is_empty() && depth >= MAX_DEPTH => script.disable(
+                    path,
+                    "on_state",
+                    "it kept reacting to changes caused by scripts".into(),
+                ),
+                Ok(msgs) => {
+                    for msg in msgs {
+                        // Only fails if the dispatcher has shut down.
+                        let _ = self.send.send(Request {
+                            msg,
+                            reply: None,
+                            depth: depth + 1,
+                        });
+                    }
+                }
+                Err(e) => script.disable(path, "on_state", e),
+            }
+        }
+    }
+}
+
+impl Script {
+    fn new(modified: SystemTime, ast: AST) -> Self {
+        Self {
+            modified,
+            ast,
+            state: RefCell::new(Map::new().into()),
+            disabled: Cell::new(false),
+        }
+    }
+
+    fn defines(&self, name: &str) -> bool {
+        self.ast.iter_functions().any(|f| f.name == name)
+    }
+
+    fn disable(&self, path: &Path, name: &str, e: Box<dyn Error>) {
+        error!(
+            "Script {} failed in {}: {}. Disabling it until it is edited.",
+            path.display(),
+            name,
+            e
+        );
+        self.disabled.set(true);
+    }
+
+    fn call(
+        &self,
+        engine: &Engine,
+        name: &str,
+        args: impl FuncArgs,
+    ) -> Result<Dynamic, Box<dyn Error>> {
+        let mut state = self.state.borrow_mut();
+        Ok(engine.call_fn_with_options(
+            CallFnOptions::new().bind_this_ptr(&mut state),
+            &mut Scope::new(),
+            &self.ast,
+            name,
+            args,
+        )?)
+    }
+
+    fn render(
+        &self,
+        engine: &Engine,
+        colors: &[Color],
+        context: Map,
+    ) -> Result<Vec<Color>, Box<dyn Error>> {
+        let result = self.call(engine, "render", (to_dynamic(colors)?, context))?;
+        let colors: Vec<Color> = from_dynamic(&result)?;
+        for color in &colors {
+            color.validate()?;
+        }
+        Ok(colors)
+    }
+
+    fn on_state(
+        &self,
+        engine: &Engine,
+        change: Dynamic,
+    ) -> Result<Vec<ControlMessage>, Box<dyn Error>> {
+        let result = self.call(engine, "on_state", (change,))?;
+        Ok(if result.is_unit() {
+            Vec::new()
+        } else if result.is_array() {
+            from_dynamic(&result)?
+        } else {
+            vec![from_dynamic(&result)?]
+        })
+    }
+}
+
+/// Return the modification time of each script in a directory.
+fn scan(dir: &Path) -> Result<BTreeMap<PathBuf, SystemTime>, Box<dyn Error>> {
+    let mut found = BTreeMap::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == "rhai") {
+            found.insert(path.clone(), fs::metadata(&path)?.modified()?);
+        }
+    }
+    Ok(found)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process;
+    use std::sync::mpsc::channel;
+
+    use shared::MasterStateChange;
+
+    #[test]
+    fn scripts_reacting_to_themselves_are_disabled() {
+        let dir = std::env::temp_dir().join(format!("palette_control_scripts_{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("echo.rhai"),
+            "fn on_state(change) { #{ Master: #{ Blackout: true } } }",
+        )
+        .unwrap();
+        let (send, recv) = channel();
+        let mut scripts = Scripts::new(Some(dir.clone()), send);
+        scripts.reload(Instant::now());
+        fs::remove_dir_all(&dir).unwrap();
+
+        // Play the dispatcher, answering each message with a state change.
+        let sc = StateChange::Master(MasterStateChange::Blackout(true));
+        scripts.on_state(&sc, 0);
+        let mut handled = 0;
+        while let Ok(req) = recv.try_recv() {
+            handled += 1;
+            assert!(handled <= MAX_DEPTH, "script was not stopped");
+            scripts.on_state(&sc, req.depth);
+        }
+        assert_eq!(handled, MAX_DEPTH);
+        assert!(scripts.defining("on_state").next().is_none());
+
+        // It stays disabled for changes that scripts did not cause.
+        scripts.on_state(&sc, 0);
+        assert!(recv.try_recv().is_err());
+    }
+}
@@ -0,0 +1,196 @@
+use std::error::Error;
+use std::time::{Duration, Instant};
+
+use shared::{lerp, Color, ColorSpace, Cue, CueControlMessage, CueStateChange, MAX_PALETTE_SIZE};
+use simple_error::bail;
+
+/// The longest a cue may fade or hold for, in seconds.
+const MAX_CUE_TIME: f32 = 24. * 60. * 60.;
+
+/// Runs a programmed list of cues, crossfading between their palettes and
+/// following on from one cue to the next.
+pub struct Sequencer {
+    cues: Vec<Cue>,
+    current: Option<usize>,
+    paused: bool,
+    /// Time spent in the current cue, not counting time spent paused.
+    elapsed: Duration,
+    last_tick: Option<Instant>,
+    fade: Option<Fade>,
+}
+
+/// A crossfade from the output at the time a cue was run.
+struct Fade {
+    from: Vec<Color>,
+    duration: Duration,
+}
+
+/// What a cue control message asks of the dispatcher.
+pub enum Action {
+    /// Sequencer state changed.
+    Changed(CueStateChange),
+    /// Run the cue at this index.
+    Run(usize),
+}
+
+/// What needs doing after time has passed.
+pub enum Tick {
+    Idle,
+    /// A fade is in progress, so the output has changed.
+    Fading,
+    /// The current cue has finished; run the cue at this index.
+    Follow(usize),
+}
+
+impl Sequencer {
+    pub fn new() -> Self {
+        Self {
+            cues: Vec::new(),
+            current: None,
+            paused: false,
+            elapsed: Duration::ZERO,
+            last_tick: None,
+            fade: None,
+        }
+    }
+
+    pub fn control(&mut self, msg: CueControlMessage) -> Result<Action, Box<dyn Error>> {
+        match msg {
+            CueControlMessage::SetCues(cues) => {
+                validate(&cues)?;
+                self.cues = cues.clone();
+                self.current = None;
+                self.fade = None;
+                Ok(Action::Changed(CueStateChange::CuesSet(cues)))
+            }
+            CueControlMessage::Go => Ok(Action::Run(self.step(1)?)),
+            CueControlMessage::Back => Ok(Action::Run(self.step(-1)?)),
+            CueControlMessage::Jump(index) => {
+                if index >= self.cues.len() {
+                    bail!(
+                        "cannot jump to cue {} of a {} cue list",
+                        index,
+                        self.cues.len()
+                    );
+                }
+                Ok(Action::Run(index))
+            }
+            CueControlMessage::Pause(paused) => {
+                self.paused = paused;
+                Ok(Action::Changed(CueStateChange::Paused(paused)))
+            }
+        }
+    }
+
+    /// Return the index of the cue offset from the current one, looping
+    /// around the ends of the list. With no cue running, Go runs the first
+    /// cue and Back the last.
+    fn step(&self, offset: isize) -> Result<usize, Box<dyn Error>> {
+        let len = self.cues.len() as isize;
+        if len == 0 {
+            bail!("the cue list is empty");
+        }
+        Ok(match self.current {
+            Some(current) => (current as isize + offset).rem_euclid(len) as usize,
+            None if offset > 0 => 0,
+            None => (len - 1) as usize,
+        })
+    }
+
+    /// Return the colors of a cue.
+    pub fn colors(&self, index: usize) -> Vec<Color> {
+        self.cues[index].colors.clone()
+    }
+
+    /// Make the cue at index current, fading from the provided output.
+    pub fn run(&mut self, index: usize, from: Vec<Color>) -> CueStateChange {
+        let duration = Duration::from_secs_f32(self.cues[index].fade);
+        self.current = Some(index);
+        self.elapsed = Duration::ZERO;
+        self.fade = (!duration.is_zero()).then_some(Fade { from, duration });
+        CueStateChange::Current(self.current)
+    }
+
+    /// Advance timers to now.
+    pub fn tick(&mut self, now: Instant) -> Tick {
+        let delta = self
+            .last_tick
+            .map_or(Duration::ZERO, |last| now.saturating_duration_since(last));
+        self.last_tick = Some(now);
+        let cue = match self.current {
+            Some(current) if !self.paused => &self.cues[current],
+            _ => return Tick::Idle,
+        };
+        self.elapsed += delta;
+        if let Some(fade) = &self.fade {
+            if self.elapsed >= fade.duration {
+                self.fade = None;
+            }
+            return Tick::Fading;
+        }
+        if cue.follow && self.elapsed.as_secs_f32() >= cue.fade + cue.hold {
+            // Validation ensures the list is not empty.
+            return Tick::Follow(self.step(1).unwrap());
+        }
+        Tick::Idle
+    }
+
+    /// Blend the live palette with the look being faded from.
+    /// Swatches the previous look did not have fade up from black.
+    pub fn render(&self, live: &[Color]) -> Vec<Color> {
+        let fade = match &self.fade {
+            Some(fade) => fade,
+            None => return live.to_vec(),
+        };
+        let t = (self.elapsed.as_secs_f32() / fade.duration.as_secs_f32()).min(1.);
+        live.iter()
+            .enumerate()
+            .map(|(i, c)| {
+                let from = fade.from.get(i).copied().unwrap_or(Color::BLACK);
+                lerp(from, *c, t, ColorSpace::default())
+            })
+            .collect()
+    }
+
+    pub fn current_state(&self) -> impl Iterator<Item = CueStateChange> {
+        [
+            CueStateChange::CuesSet(self.cues.clone()),
+            CueStateChange::Current(self.current),
+            CueStateChange::Paused(self.paused),
+        ]
+        .into_iter()
+    }
+}
+
+fn validate(cues: &[Cue]) -> Result<(), Box<dyn Error>> {
+    for (i, cue) in cues.iter().enumerate() {
+        if cue.colors.len() > MAX_PALETTE_SIZE {
+            bail!(
+                "cue {} has {} colors; the most is {}",
+                i,
+                cue.colors.len(),
+                MAX_PALETTE_SIZE
+            );
+        }
+        for (j, color) in cue.colors.iter().enumerate() {
+            color
+                .validate()
+                .map_err(|e| format!("cue {} color {}: {}", i, j, e))?;
+        }
+        for (name, time) in [("fade", cue.fade), ("hold", cue.hold)] {
+            if !(0. ..=MAX_CUE_TIME).contains(&time) {
+                bail!(
+                    "cue {}: invalid {} time {}; it must be between 0 and {} seconds",
+                    i,
+                    name,
+                    time,
+                    MAX_CUE_TIME
+                );
+            }
+        }
+        if cue.follow && cue.fade + cue.hold <= 0. {
+            bail!("cue {} follows on with no fade or hold time", i);
+        }
+    }
+    Ok(())
+}
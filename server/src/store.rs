@@ -0,0 +1,56 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+use shared::{Color, ScheduleId, Scheduled};
+
+/// Server state that survives a restart.
+#[derive(Default, Serialize, Deserialize)]
+pub struct SavedState {
+    #[serde(default)]
+    pub palettes: BTreeMap<String, Vec<Color>>,
+    #[serde(default)]
+    pub schedule: Vec<(ScheduleId, Scheduled)>,
+}
+
+/// Reads and writes saved state as a JSON file.
+/// Without a path, nothing is persisted.
+pub struct Store {
+    path: Option<PathBuf>,
+}
+
+impl Store {
+    pub fn new(path: Option<PathBuf>) -> Self {
+        Self { path }
+    }
+
+    /// Load saved state. A missing file is an empty state.
+    pub fn load(&self) -> Result<SavedState, Box<dyn Error>> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(SavedState::default()),
+        };
+        match fs::read(path) {
+            Ok(data) => Ok(serde_json::from_slice(&data)
+                .map_err(|e| format!("invalid state file {}: {}", path.display(), e))?),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(SavedState::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Write state, replacing the file atomically so a crash mid-write
+    /// leaves the previous state intact.
+    pub fn save(&self, state: &SavedState) -> Result<(), Box<dyn Error>> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_vec_pretty(state)?)?;
+        fs::rename(tmp, path)?;
+        Ok(())
+    }
+}
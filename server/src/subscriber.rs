@@ -5,9 +5,10 @@ use crate::osc::OscSender;
 use log::error;
 use rosc::{encoder, OscMessage, OscType};
 use shared::{
-    Color, Subscriber, SubscriberConfig, SubscriberControlMessage, SubscriberId,
-    SubscriberStateChange,
+    unipolar_float_to_u16, unipolar_float_to_u8, Channels, Color, Encoding, OutputCurve,
+    Subscriber, SubscriberConfig, SubscriberControlMessage, SubscriberId, SubscriberStateChange,
 };
+use simple_error::bail;
 
 /// Maintain the collection of palette subscribers.
 pub struct Subscribers {
@@ -23,19 +24,56 @@ impl Subscribers {
         }
     }
 
-    pub fn control(&mut self, msg: SubscriberControlMessage) -> SubscriberStateChange {
-        match msg {
+    pub fn control(
+        &mut self,
+        msg: SubscriberControlMessage,
+    ) -> Result<SubscriberStateChange, Box<dyn Error>> {
+        Ok(match msg {
             SubscriberControlMessage::Add(cfg) => {
                 let id = self.next_id;
                 self.next_id.advance();
-                let sub = Subscriber { id, cfg };
+                let sub = Subscriber {
+                    id,
+                    cfg,
+                    curve: OutputCurve::default(),
+                    channels: Channels::default(),
+                    encoding: Encoding::default(),
+                };
                 self.subs.push(sub.clone());
                 SubscriberStateChange::Added(sub)
             }
             SubscriberControlMessage::Remove(id) => {
-                self.subs.retain(|sub| sub.id != id);
+                let index = self.index(id)?;
+                self.subs.remove(index);
                 SubscriberStateChange::Removed(id)
             }
+            SubscriberControlMessage::SetCurve(id, curve) => {
+                if !curve.is_valid() {
+                    bail!("invalid output curve for subscriber {}: {:?}", id, curve);
+                }
+                self.get_mut(id)?.curve = curve.clone();
+                SubscriberStateChange::CurveSet(id, curve)
+            }
+            SubscriberControlMessage::SetChannels(id, channels) => {
+                self.get_mut(id)?.channels = channels;
+                SubscriberStateChange::ChannelsSet(id, channels)
+            }
+            SubscriberControlMessage::SetEncoding(id, encoding) => {
+                self.get_mut(id)?.encoding = encoding;
+                SubscriberStateChange::EncodingSet(id, encoding)
+            }
+        })
+    }
+
+    fn get_mut(&mut self, id: SubscriberId) -> Result<&mut Subscriber, Box<dyn Error>> {
+        let index = self.index(id)?;
+        Ok(&mut self.subs[index])
+    }
+
+    fn index(&self, id: SubscriberId) -> Result<usize, Box<dyn Error>> {
+        match self.subs.iter().position(|s| s.id == id) {
+            Some(index) => Ok(index),
+            None => bail!("no subscriber found with ID {}", id),
         }
     }
 
@@ -48,52 +86,59 @@ impl Subscribers {
     /// Send the provided palette to all subscribers.
     /// Logs errors.
     pub fn send_palette(&self, colors: &[Color], osc_sender: &OscSender) {
-        let osc_encoded = match prepare_osc_palette(colors) {
-            Ok(msg) => msg,
-            Err(e) => {
-                error!("Unable to encode OSC message: {}.", e);
-                return;
-            }
-        };
         for sub in self.subs.iter() {
-            self.send_to(sub, osc_encoded.clone(), osc_sender);
+            self.send_to(sub, colors, osc_sender);
         }
     }
 
     pub fn send_palette_to(&self, id: SubscriberId, colors: &[Color], osc_sender: &OscSender) {
         // Find the right subscriber.
-        let sub = if let Some(sub) = self.subs.iter().filter(|s| s.id == id).next() {
+        let sub = if let Some(sub) = self.subs.iter().find(|s| s.id == id) {
             sub
         } else {
             error!("No subscriber found with ID {}.", id);
             return;
         };
-        let osc_encoded = match prepare_osc_palette(colors) {
-            Ok(msg) => msg,
-            Err(e) => {
-                error!("Unable to encode OSC message: {}.", e);
-                return;
-            }
-        };
-        self.send_to(sub, osc_encoded, osc_sender);
+        self.send_to(sub, colors, osc_sender);
     }
 
-    fn send_to(&self, sub: &Subscriber, osc_encoded: Arc<Vec<u8>>, osc_sender: &OscSender) {
+    /// Fill in the subscriber's channels, apply its output curve, encode,
+    /// and send.
+    fn send_to(&self, sub: &Subscriber, colors: &[Color], osc_sender: &OscSender) {
+        let corrected: Vec<Color> = colors
+            .iter()
+            .map(|c| sub.curve.apply(sub.channels.prepare(*c)))
+            .collect();
         use SubscriberConfig::*;
         match sub.cfg {
-            Osc(addr) => {
-                osc_sender.send(addr, osc_encoded);
-            }
+            Osc(addr) => match prepare_osc_palette(&corrected, &sub.channels, sub.encoding) {
+                Ok(osc_encoded) => osc_sender.send(addr, osc_encoded),
+                Err(e) => error!("Unable to encode OSC message: {}.", e),
+            },
         }
     }
 }
 
-fn prepare_osc_palette(colors: &[Color]) -> Result<Arc<Vec<u8>>, Box<dyn Error>> {
-    let mut osc_args = Vec::with_capacity(colors.len() * 3);
-    for color in colors {
-        osc_args.push(OscType::Float(color.red));
-        osc_args.push(OscType::Float(color.green));
-        osc_args.push(OscType::Float(color.blue));
+/// Encode a palette as a single OSC message.
+/// Each color is sent as consecutive float args, red, green and blue followed
+/// by any extra channels the subscriber wants.
+fn prepare_osc_palette(
+    colors: &[Color],
+    channels: &Channels,
+    encoding: Encoding,
+) -> Result<Arc<Vec<u8>>, Box<dyn Error>> {
+    let mut osc_args = Vec::new();
+    for value in colors.iter().flat_map(|color| channels.values(color)) {
+        match encoding {
+            Encoding::Float => osc_args.push(OscType::Float(value)),
+            Encoding::Int8 => osc_args.push(OscType::Int(unipolar_float_to_u8(value) as i32)),
+            Encoding::Int16 => osc_args.push(OscType::Int(unipolar_float_to_u16(value) as i32)),
+            Encoding::CoarseFine => {
+                let [coarse, fine] = unipolar_float_to_u16(value).to_be_bytes();
+                osc_args.push(OscType::Int(coarse as i32));
+                osc_args.push(OscType::Int(fine as i32));
+            }
+        }
     }
     let osc_msg = OscMessage {
         addr: "/palette".to_string(),
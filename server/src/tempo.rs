@@ -0,0 +1,76 @@
+use std::error::Error;
+use std::net::{Ipv4Addr, SocketAddrV4, UdpSocket};
+use std::sync::mpsc::Sender;
+use std::thread;
+
+use log::{debug, error, info, warn};
+use rosc::{decoder, OscMessage, OscPacket, OscType};
+use shared::{ClockControlMessage, ControlMessage};
+
+use crate::control::Request;
+
+/// Listen for tempo messages over OSC from external software such as a DJ
+/// application, and drive the tempo clock with them.
+///
+/// `/beat` marks a beat landing now. `/bpm <number>` sets the tempo.
+pub fn listen(port: u16, send: Sender<Request>) -> Result<(), Box<dyn Error>> {
+    let sock = UdpSocket::bind(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, port))?;
+    info!("Listening for OSC tempo messages on port {}.", port);
+    thread::spawn(move || {
+        let mut buf = [0u8; decoder::MTU];
+        loop {
+            let len = match sock.recv(&mut buf) {
+                Ok(len) => len,
+                Err(e) => {
+                    error!("OSC tempo input error: {}.", e);
+                    continue;
+                }
+            };
+            let packet = match decoder::decode_udp(&buf[..len]) {
+                Ok((_, packet)) => packet,
+                Err(e) => {
+                    warn!("Unable to decode OSC tempo message: {}.", e);
+                    continue;
+                }
+            };
+            for msg in flatten(packet) {
+                let control = match tempo_message(&msg) {
+                    Some(control) => control,
+                    None => {
+                        debug!("Ignoring OSC message {:?}.", msg);
+                        continue;
+                    }
+                };
+                if send.send(ControlMessage::Clock(control).into()).is_err() {
+                    // The dispatcher has shut down.
+                    return;
+                }
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Return the messages in a packet, unpacking bundles.
+fn flatten(packet: OscPacket) -> Vec<OscMessage> {
+    match packet {
+        OscPacket::Message(msg) => vec![msg],
+        OscPacket::Bundle(bundle) => bundle.content.into_iter().flat_map(flatten).collect(),
+    }
+}
+
+fn tempo_message(msg: &OscMessage) -> Option<ClockControlMessage> {
+    match msg.addr.as_str() {
+        "/beat" => Some(ClockControlMessage::Beat),
+        "/bpm" => {
+            let bpm = match msg.args.first()? {
+                OscType::Float(v) => *v,
+                OscType::Double(v) => *v as f32,
+                OscType::Int(v) => *v as f32,
+                _ => return None,
+            };
+            Some(ClockControlMessage::SetBpm(bpm))
+        }
+        _ => None,
+    }
+}
@@ -0,0 +1,170 @@
+use std::error::Error;
+use std::fs;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::TcpStream;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use rustls::{Certificate, PrivateKey, ServerConfig, ServerConnection};
+use rustls_pemfile::Item;
+use simple_error::bail;
+
+/// The most ciphertext to read from a socket at once.
+const READ_SIZE: usize = 4096;
+
+/// A certificate chain and private key to serve TLS with.
+#[derive(Clone)]
+pub struct Tls {
+    cert_pem: Vec<u8>,
+    key_pem: Vec<u8>,
+    config: Arc<ServerConfig>,
+}
+
+impl Tls {
+    /// Load a certificate chain and private key from PEM files.
+    pub fn load(cert: &Path, key: &Path) -> Result<Self, Box<dyn Error>> {
+        let cert_pem = fs::read(cert)?;
+        let key_pem = fs::read(key)?;
+        let mut certs = Vec::new();
+        for item in rustls_pemfile::read_all(&mut cert_pem.as_slice())? {
+            if let Item::X509Certificate(der) = item {
+                certs.push(Certificate(der));
+            }
+        }
+        if certs.is_empty() {
+            bail!("no certificates found in {}", cert.display());
+        }
+        let key_der = rustls_pemfile::read_all(&mut key_pem.as_slice())?
+            .into_iter()
+            .find_map(|item| match item {
+                Item::RSAKey(der) | Item::PKCS8Key(der) | Item::ECKey(der) => Some(der),
+                _ => None,
+            });
+        let key_der = match key_der {
+            Some(der) => der,
+            None => bail!("no private key found in {}", key.display()),
+        };
+        let config = ServerConfig::builder()
+            .with_safe_defaults()
+            .with_no_client_auth()
+            .with_single_cert(certs, PrivateKey(key_der))?;
+        Ok(Self {
+            cert_pem,
+            key_pem,
+            config: Arc::new(config),
+        })
+    }
+
+    /// Begin a TLS session with a client. The handshake happens as the
+    /// connection is first read.
+    pub fn accept(&self, sock: TcpStream) -> Result<Connection, Box<dyn Error>> {
+        let session = ServerConnection::new(self.config.clone())?;
+        Ok(Connection::Tls {
+            session: Arc::new(Mutex::new(session)),
+            sock,
+        })
+    }
+
+    pub fn ssl_config(&self) -> tiny_http::SslConfig {
+        tiny_http::SslConfig {
+            certificate: self.cert_pem.clone(),
+            private_key: self.key_pem.clone(),
+        }
+    }
+}
+
+/// A client connection, which may be encrypted.
+///
+/// Clones share the connection, so one thread can block reading from a
+/// clone while others write to it.
+pub enum Connection {
+    Plain(TcpStream),
+    Tls {
+        /// TLS state, shared by all clones.
+        session: Arc<Mutex<ServerConnection>>,
+        sock: TcpStream,
+    },
+}
+
+impl Connection {
+    pub fn try_clone(&self) -> io::Result<Self> {
+        Ok(match self {
+            Connection::Plain(sock) => Connection::Plain(sock.try_clone()?),
+            Connection::Tls { session, sock } => Connection::Tls {
+                session: session.clone(),
+                sock: sock.try_clone()?,
+            },
+        })
+    }
+}
+
+impl Read for Connection {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let (session, sock) = match self {
+            Connection::Plain(sock) => return sock.read(buf),
+            Connection::Tls { session, sock } => (session, sock),
+        };
+        let mut ciphertext = [0; READ_SIZE];
+        loop {
+            {
+                let mut session = session.lock().unwrap();
+                match session.reader().read(buf) {
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => (),
+                    result => return result,
+                }
+                // The handshake may need answering before any data arrives.
+                flush_tls(&mut session, sock)?;
+            }
+            // Wait for more without holding the session, so writers aren't
+            // blocked by an idle reader.
+            let len = sock.read(&mut ciphertext)?;
+            let mut session = session.lock().unwrap();
+            // An empty read tells the session the socket closed, and it
+            // decides whether that was clean.
+            let mut received = &ciphertext[..len];
+            loop {
+                session.read_tls(&mut received)?;
+                session
+                    .process_new_packets()
+                    .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
+                if received.is_empty() {
+                    break;
+                }
+            }
+            flush_tls(&mut session, sock)?;
+        }
+    }
+}
+
+impl Write for Connection {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Connection::Plain(sock) => sock.write(buf),
+            Connection::Tls { session, sock } => {
+                let mut session = session.lock().unwrap();
+                let len = session.writer().write(buf)?;
+                flush_tls(&mut session, sock)?;
+                Ok(len)
+            }
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Connection::Plain(sock) => sock.flush(),
+            Connection::Tls { session, sock } => {
+                let mut session = session.lock().unwrap();
+                session.writer().flush()?;
+                flush_tls(&mut session, sock)
+            }
+        }
+    }
+}
+
+/// Send any ciphertext the session has ready.
+fn flush_tls(session: &mut ServerConnection, sock: &mut TcpStream) -> io::Result<()> {
+    while session.wants_write() {
+        session.write_tls(sock)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,76 @@
+use serde::{Deserialize, Serialize};
+
+use crate::Color;
+
+/// The emitters a subscriber wants values for, in addition to red, green
+/// and blue.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(default)]
+pub struct Channels {
+    pub white: bool,
+    pub amber: bool,
+    pub uv: bool,
+    /// How to fill in white for colors that don't specify it.
+    pub derive_white: WhiteDerivation,
+}
+
+/// Strategies for producing a white level from a color authored in RGB.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum WhiteDerivation {
+    /// Leave white off.
+    #[default]
+    Off,
+    /// Move the component common to red, green and blue into white.
+    /// Preserves hue and overall intensity.
+    Extract,
+    /// Add the component common to red, green and blue as white, leaving
+    /// the other channels untouched. Brighter, but less saturated.
+    Boost,
+}
+
+impl Channels {
+    /// Fill in all of the channels this subscriber wants.
+    /// Channels the subscriber doesn't want are dropped.
+    pub fn prepare(&self, color: Color) -> Color {
+        let mut color = color;
+        if self.white && color.white.is_none() {
+            let common = color.red.min(color.green).min(color.blue).max(0.);
+            match self.derive_white {
+                WhiteDerivation::Off => {
+                    color.white = Some(0.);
+                }
+                WhiteDerivation::Extract => {
+                    color.red -= common;
+                    color.green -= common;
+                    color.blue -= common;
+                    color.white = Some(common);
+                }
+                WhiteDerivation::Boost => {
+                    color.white = Some(common);
+                }
+            }
+        }
+        Color {
+            white: self.white.then(|| color.white.unwrap_or(0.)),
+            amber: self.amber.then(|| color.amber.unwrap_or(0.)),
+            uv: self.uv.then(|| color.uv.unwrap_or(0.)),
+            ..color
+        }
+    }
+
+    /// Return the values to send for a prepared color, in the order
+    /// red, green, blue, white, amber, UV, omitting unwanted channels.
+    pub fn values(&self, color: &Color) -> Vec<f32> {
+        let mut values = vec![color.red, color.green, color.blue];
+        for (wanted, value) in [
+            (self.white, color.white),
+            (self.amber, color.amber),
+            (self.uv, color.uv),
+        ] {
+            if wanted {
+                values.push(value.unwrap_or(0.));
+            }
+        }
+        values
+    }
+}
@@ -0,0 +1,648 @@
+use std::f32::consts::TAU;
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::Cct;
+
+/// A color in RGB space, with each component a unit float.
+/// Colors may also carry levels for white, amber and UV emitters; if these
+/// are absent, subscribers derive them as configured.
+///
+/// Serializes as an RGB struct; deserializes from the RGB struct, a hex
+/// string, or an HSV or HSL struct.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(try_from = "ColorRepr")]
+pub struct Color {
+    pub red: f32,
+    pub green: f32,
+    pub blue: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub white: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub amber: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub uv: Option<f32>,
+}
+
+impl Color {
+    pub const BLACK: Color = Color::rgb(0., 0., 0.);
+
+    /// Create a color with only red, green and blue components.
+    pub const fn rgb(red: f32, green: f32, blue: f32) -> Self {
+        Self {
+            red,
+            green,
+            blue,
+            white: None,
+            amber: None,
+            uv: None,
+        }
+    }
+
+    /// Scale all components of this color by the provided unit factor.
+    pub fn scaled(&self, level: f32) -> Color {
+        Color {
+            red: self.red * level,
+            green: self.green * level,
+            blue: self.blue * level,
+            white: self.white.map(|v| v * level),
+            amber: self.amber.map(|v| v * level),
+            uv: self.uv.map(|v| v * level),
+        }
+    }
+
+    pub fn as_u8(&self) -> (u8, u8, u8) {
+        (
+            unipolar_float_to_u8(self.red),
+            unipolar_float_to_u8(self.green),
+            unipolar_float_to_u8(self.blue),
+        )
+    }
+
+    pub fn as_u16(&self) -> (u16, u16, u16) {
+        (
+            unipolar_float_to_u16(self.red),
+            unipolar_float_to_u16(self.green),
+            unipolar_float_to_u16(self.blue),
+        )
+    }
+
+    /// Create a color, checking that each component is a unit float.
+    pub fn new(red: f32, green: f32, blue: f32) -> Result<Self, InvalidColor> {
+        let color = Self::rgb(red, green, blue);
+        color.validate()?;
+        Ok(color)
+    }
+
+    /// Check that every component present is a finite unit float.
+    pub fn validate(&self) -> Result<(), InvalidColor> {
+        for (channel, value) in [
+            ("red", Some(self.red)),
+            ("green", Some(self.green)),
+            ("blue", Some(self.blue)),
+            ("white", self.white),
+            ("amber", self.amber),
+            ("uv", self.uv),
+        ] {
+            if let Some(value) = value {
+                if !(0.0..=1.0).contains(&value) {
+                    return Err(InvalidColor { channel, value });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Create a white from a color temperature in Kelvin, optionally offset
+    /// toward green (positive tint) or magenta (negative tint).
+    pub fn from_kelvin(kelvin: f32, tint: f32) -> Self {
+        Cct { kelvin, tint }.into()
+    }
+
+    pub fn from_u8(red: u8, green: u8, blue: u8) -> Self {
+        Self::rgb(red as f32 / 255., green as f32 / 255., blue as f32 / 255.)
+    }
+
+    /// Format this color as a CSS-style hex string, like #ff8000.
+    pub fn to_hex(&self) -> String {
+        let (r, g, b) = self.as_u8();
+        format!("#{:02x}{:02x}{:02x}", r, g, b)
+    }
+
+    /// Parse a hex string like #ff8000; the leading # is optional.
+    pub fn from_hex(s: &str) -> Result<Self, ParseColorError> {
+        let digits = s.strip_prefix('#').unwrap_or(s);
+        if digits.len() != 6 || !digits.is_ascii() {
+            return Err(ParseColorError(s.to_string()));
+        }
+        let channel = |i: usize| {
+            u8::from_str_radix(&digits[i..i + 2], 16).map_err(|_| ParseColorError(s.to_string()))
+        };
+        Ok(Self::from_u8(channel(0)?, channel(2)?, channel(4)?))
+    }
+
+    /// Return the largest and smallest components, and the hue as a unit
+    /// float. Hue is zero for grays.
+    fn hue_extrema(&self) -> (f32, f32, f32) {
+        let Color {
+            red, green, blue, ..
+        } = *self;
+        let max = red.max(green).max(blue);
+        let min = red.min(green).min(blue);
+        let delta = max - min;
+        let sextant = if delta == 0. {
+            0.
+        } else if max == red {
+            ((green - blue) / delta).rem_euclid(6.)
+        } else if max == green {
+            (blue - red) / delta + 2.
+        } else {
+            (red - green) / delta + 4.
+        };
+        (max, min, sextant / 6.)
+    }
+
+    /// Build a color from a hue, chroma, and the offset added to all
+    /// components.
+    fn from_hue_chroma(hue: f32, chroma: f32, offset: f32) -> Self {
+        let sextant = hue.rem_euclid(1.) * 6.;
+        let x = chroma * (1. - (sextant.rem_euclid(2.) - 1.).abs());
+        let (red, green, blue) = match sextant as u8 {
+            0 => (chroma, x, 0.),
+            1 => (x, chroma, 0.),
+            2 => (0., chroma, x),
+            3 => (0., x, chroma),
+            4 => (x, 0., chroma),
+            _ => (chroma, 0., x),
+        };
+        Self::rgb(red + offset, green + offset, blue + offset)
+    }
+}
+
+/// Convert a unit float to an 8-bit integer.
+/// Uses rounding instead of floor to ensure we divide up the unit range into
+/// bins of equal size.
+pub fn unipolar_float_to_u8(f: f32) -> u8 {
+    (f * 255.).round() as u8
+}
+
+/// Convert a unit float to a 16-bit integer, rounding as for 8 bits.
+pub fn unipolar_float_to_u16(f: f32) -> u16 {
+    (f * 65535.).round() as u16
+}
+
+impl FromStr for Color {
+    type Err = ParseColorError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::from_hex(s)
+    }
+}
+
+/// A color in hue/saturation/value space.
+/// All components are unit floats; hue is a fraction of a full turn.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct Hsv {
+    pub hue: f32,
+    pub saturation: f32,
+    pub value: f32,
+}
+
+impl From<Color> for Hsv {
+    fn from(c: Color) -> Self {
+        let (max, min, hue) = c.hue_extrema();
+        Self {
+            hue,
+            saturation: if max == 0. { 0. } else { (max - min) / max },
+            value: max,
+        }
+    }
+}
+
+impl From<Hsv> for Color {
+    fn from(c: Hsv) -> Self {
+        let chroma = c.value * c.saturation;
+        Color::from_hue_chroma(c.hue, chroma, c.value - chroma)
+    }
+}
+
+/// A color in hue/saturation/lightness space.
+/// All components are unit floats; hue is a fraction of a full turn.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct Hsl {
+    pub hue: f32,
+    pub saturation: f32,
+    pub lightness: f32,
+}
+
+impl From<Color> for Hsl {
+    fn from(c: Color) -> Self {
+        let (max, min, hue) = c.hue_extrema();
+        let lightness = (max + min) / 2.;
+        let saturation = if max == min {
+            0.
+        } else {
+            (max - min) / (1. - (2. * lightness - 1.).abs())
+        };
+        Self {
+            hue,
+            saturation,
+            lightness,
+        }
+    }
+}
+
+impl From<Hsl> for Color {
+    fn from(c: Hsl) -> Self {
+        let chroma = (1. - (2. * c.lightness - 1.).abs()) * c.saturation;
+        Color::from_hue_chroma(c.hue, chroma, c.lightness - chroma / 2.)
+    }
+}
+
+/// A color in the OKLab perceptual color space.
+/// Conversions assume Color holds sRGB-encoded components.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct Oklab {
+    pub lightness: f32,
+    pub a: f32,
+    pub b: f32,
+}
+
+// Matrices are kept exactly as published by Björn Ottosson.
+#[allow(clippy::excessive_precision)]
+impl From<Color> for Oklab {
+    fn from(c: Color) -> Self {
+        let (r, g, b) = (
+            srgb_to_linear(c.red),
+            srgb_to_linear(c.green),
+            srgb_to_linear(c.blue),
+        );
+        let l = (0.4122214708 * r + 0.5363325363 * g + 0.0514459929 * b).cbrt();
+        let m = (0.2119034982 * r + 0.6806995451 * g + 0.1073969566 * b).cbrt();
+        let s = (0.0883024619 * r + 0.2817188376 * g + 0.6299787005 * b).cbrt();
+        Self {
+            lightness: 0.2104542553 * l + 0.7936177850 * m - 0.0040720468 * s,
+            a: 1.9779984951 * l - 2.4285922050 * m + 0.4505937099 * s,
+            b: 0.0259040371 * l + 0.7827717662 * m - 0.8086757660 * s,
+        }
+    }
+}
+
+#[allow(clippy::excessive_precision)]
+impl From<Oklab> for Color {
+    /// Colors outside of the sRGB gamut are clamped.
+    fn from(c: Oklab) -> Self {
+        let l = (c.lightness + 0.3963377774 * c.a + 0.2158037573 * c.b).powi(3);
+        let m = (c.lightness - 0.1055613458 * c.a - 0.0638541728 * c.b).powi(3);
+        let s = (c.lightness - 0.0894841775 * c.a - 1.2914855480 * c.b).powi(3);
+        Self::rgb(
+            linear_to_srgb(4.0767416621 * l - 3.3077115913 * m + 0.2309699292 * s),
+            linear_to_srgb(-1.2684380046 * l + 2.6097574011 * m - 0.3413193965 * s),
+            linear_to_srgb(-0.0041960863 * l - 0.7034186147 * m + 1.7076147010 * s),
+        )
+    }
+}
+
+/// OKLab in polar form: lightness, chroma and hue.
+/// Hue is a unit float, a fraction of a full turn.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct Oklch {
+    pub lightness: f32,
+    pub chroma: f32,
+    pub hue: f32,
+}
+
+impl From<Oklab> for Oklch {
+    fn from(c: Oklab) -> Self {
+        Self {
+            lightness: c.lightness,
+            chroma: c.a.hypot(c.b),
+            hue: (c.b.atan2(c.a) / TAU).rem_euclid(1.),
+        }
+    }
+}
+
+impl From<Oklch> for Oklab {
+    fn from(c: Oklch) -> Self {
+        let angle = c.hue * TAU;
+        Self {
+            lightness: c.lightness,
+            a: c.chroma * angle.cos(),
+            b: c.chroma * angle.sin(),
+        }
+    }
+}
+
+impl From<Color> for Oklch {
+    fn from(c: Color) -> Self {
+        Oklab::from(c).into()
+    }
+}
+
+impl From<Oklch> for Color {
+    fn from(c: Oklch) -> Self {
+        Oklab::from(c).into()
+    }
+}
+
+/// Decode an sRGB component into linear light.
+fn srgb_to_linear(v: f32) -> f32 {
+    if v <= 0.04045 {
+        v / 12.92
+    } else {
+        ((v + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Encode a linear light component into sRGB, clamping to the unit range.
+pub(crate) fn linear_to_srgb(v: f32) -> f32 {
+    let v = v.clamp(0., 1.);
+    if v <= 0.0031308 {
+        v * 12.92
+    } else {
+        1.055 * v.powf(1. / 2.4) - 0.055
+    }
+}
+
+/// The representations a color may be deserialized from.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ColorRepr {
+    Rgb {
+        red: f32,
+        green: f32,
+        blue: f32,
+        #[serde(default)]
+        white: Option<f32>,
+        #[serde(default)]
+        amber: Option<f32>,
+        #[serde(default)]
+        uv: Option<f32>,
+    },
+    Hex(String),
+    Hsv(Hsv),
+    Hsl(Hsl),
+}
+
+impl TryFrom<ColorRepr> for Color {
+    type Error = ParseColorError;
+
+    fn try_from(repr: ColorRepr) -> Result<Self, Self::Error> {
+        Ok(match repr {
+            ColorRepr::Rgb {
+                red,
+                green,
+                blue,
+                white,
+                amber,
+                uv,
+            } => Color {
+                red,
+                green,
+                blue,
+                white,
+                amber,
+                uv,
+            },
+            ColorRepr::Hex(s) => Color::from_hex(&s)?,
+            ColorRepr::Hsv(c) => c.into(),
+            ColorRepr::Hsl(c) => c.into(),
+        })
+    }
+}
+
+/// A string could not be parsed as a color.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseColorError(String);
+
+impl fmt::Display for ParseColorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid hex color \"{}\"", self.0)
+    }
+}
+
+impl std::error::Error for ParseColorError {}
+
+/// A color component was NaN, infinite, or outside of the unit range.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InvalidColor {
+    pub channel: &'static str,
+    pub value: f32,
+}
+
+impl fmt::Display for InvalidColor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid {} level {}", self.channel, self.value)
+    }
+}
+
+impl std::error::Error for InvalidColor {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPSILON: f32 = 1e-5;
+
+    /// Colors covering each sextant of the hue wheel, grays, and extremes.
+    fn samples() -> Vec<Color> {
+        let steps = [0., 0.2, 0.5, 0.8, 1.];
+        let mut colors = Vec::new();
+        for red in steps {
+            for green in steps {
+                for blue in steps {
+                    colors.push(Color::rgb(red, green, blue));
+                }
+            }
+        }
+        colors
+    }
+
+    fn assert_close(a: Color, b: Color, epsilon: f32) {
+        assert!(
+            (a.red - b.red).abs() < epsilon
+                && (a.green - b.green).abs() < epsilon
+                && (a.blue - b.blue).abs() < epsilon,
+            "{:?} != {:?}",
+            a,
+            b
+        );
+    }
+
+    #[test]
+    fn hsv_round_trip() {
+        for c in samples() {
+            assert_close(Color::from(Hsv::from(c)), c, EPSILON);
+        }
+    }
+
+    #[test]
+    fn hsl_round_trip() {
+        for c in samples() {
+            assert_close(Color::from(Hsl::from(c)), c, EPSILON);
+        }
+    }
+
+    #[test]
+    fn grays_have_no_saturation() {
+        for level in [0., 0.5, 1.] {
+            let gray = Color::rgb(level, level, level);
+            let hsv = Hsv::from(gray);
+            assert_eq!((hsv.hue, hsv.saturation, hsv.value), (0., 0., level));
+            let hsl = Hsl::from(gray);
+            assert_eq!((hsl.hue, hsl.saturation, hsl.lightness), (0., 0., level));
+        }
+    }
+
+    #[test]
+    fn full_turn_hue_is_red() {
+        let red = Color::rgb(1., 0., 0.);
+        let hsv = Hsv {
+            hue: 1.,
+            saturation: 1.,
+            value: 1.,
+        };
+        assert_close(hsv.into(), red, EPSILON);
+        let hsl = Hsl {
+            hue: 1.,
+            saturation: 1.,
+            lightness: 0.5,
+        };
+        assert_close(hsl.into(), red, EPSILON);
+    }
+
+    /// sRGB primaries and white in OKLab, as given in CSS Color Module
+    /// Level 4.
+    const OKLAB_REFERENCE: [(Color, Oklab); 4] = [
+        (
+            Color::rgb(1., 1., 1.),
+            Oklab {
+                lightness: 1.,
+                a: 0.,
+                b: 0.,
+            },
+        ),
+        (
+            Color::rgb(1., 0., 0.),
+            Oklab {
+                lightness: 0.627955,
+                a: 0.224863,
+                b: 0.125846,
+            },
+        ),
+        (
+            Color::rgb(0., 1., 0.),
+            Oklab {
+                lightness: 0.866440,
+                a: -0.233888,
+                b: 0.179498,
+            },
+        ),
+        (
+            Color::rgb(0., 0., 1.),
+            Oklab {
+                lightness: 0.452014,
+                a: -0.032457,
+                b: -0.311528,
+            },
+        ),
+    ];
+
+    /// The published values are rounded, and the conversions lose some
+    /// precision to f32 cube roots.
+    const OKLAB_EPSILON: f32 = 1e-4;
+
+    #[test]
+    fn oklab_reference_values() {
+        for (color, expected) in OKLAB_REFERENCE {
+            let lab = Oklab::from(color);
+            assert!(
+                (lab.lightness - expected.lightness).abs() < OKLAB_EPSILON
+                    && (lab.a - expected.a).abs() < OKLAB_EPSILON
+                    && (lab.b - expected.b).abs() < OKLAB_EPSILON,
+                "{:?} converted to {:?}, expected {:?}",
+                color,
+                lab,
+                expected
+            );
+            assert_close(expected.into(), color, OKLAB_EPSILON);
+        }
+    }
+
+    #[test]
+    fn oklch_reference_values() {
+        // Red and blue in OKLCh, with hue in degrees.
+        for (color, lightness, chroma, degrees) in [
+            (Color::rgb(1., 0., 0.), 0.627955, 0.257683, 29.2339),
+            (Color::rgb(0., 0., 1.), 0.452014, 0.313214, 264.052),
+        ] {
+            let lch = Oklch::from(color);
+            assert!((lch.lightness - lightness).abs() < OKLAB_EPSILON);
+            assert!((lch.chroma - chroma).abs() < OKLAB_EPSILON);
+            assert!((lch.hue * 360. - degrees).abs() < 0.01, "{:?}", lch);
+            assert_close(lch.into(), color, OKLAB_EPSILON);
+        }
+    }
+
+    #[test]
+    fn oklab_round_trip() {
+        for c in samples() {
+            assert_close(Color::from(Oklab::from(c)), c, OKLAB_EPSILON);
+            assert_close(Color::from(Oklch::from(c)), c, OKLAB_EPSILON);
+        }
+    }
+
+    #[test]
+    fn hex_round_trip() {
+        for c in samples() {
+            let hex = c.to_hex();
+            assert_eq!(Color::from_hex(&hex).unwrap().to_hex(), hex);
+        }
+        for hex in ["#000000", "#ffffff", "#ff8000", "#0a1b2c"] {
+            assert_eq!(Color::from_hex(hex).unwrap().to_hex(), hex);
+        }
+    }
+
+    #[test]
+    fn hex_parsing() {
+        assert_eq!(
+            Color::from_hex("FF8000").unwrap(),
+            Color::from_u8(255, 128, 0)
+        );
+        assert_eq!(
+            "#ff8000".parse::<Color>().unwrap(),
+            Color::from_u8(255, 128, 0)
+        );
+        for invalid in ["", "#", "#fff", "#ff80000", "#gg0000", "#ff800", "ffé00"] {
+            assert_eq!(
+                Color::from_hex(invalid),
+                Err(ParseColorError(invalid.to_string()))
+            );
+        }
+    }
+
+    fn from_json(json: &str) -> Result<Color, serde_json::Error> {
+        serde_json::from_str(json)
+    }
+
+    #[test]
+    fn deserialize_rgb() {
+        assert_eq!(
+            from_json(r#"{"red":1.0,"green":0.5,"blue":0.0,"white":0.25}"#).unwrap(),
+            Color {
+                white: Some(0.25),
+                ..Color::rgb(1., 0.5, 0.)
+            }
+        );
+    }
+
+    #[test]
+    fn deserialize_hex() {
+        assert_eq!(
+            from_json(r##""#ff8000""##).unwrap(),
+            Color::from_u8(255, 128, 0)
+        );
+        assert_eq!(from_json(r#""00ff00""#).unwrap(), Color::rgb(0., 1., 0.));
+    }
+
+    #[test]
+    fn deserialize_hsv() {
+        let c = from_json(r#"{"hue":0.5,"saturation":1.0,"value":1.0}"#).unwrap();
+        assert_close(c, Color::rgb(0., 1., 1.), EPSILON);
+    }
+
+    #[test]
+    fn deserialize_hsl() {
+        let c = from_json(r#"{"hue":0.0,"saturation":1.0,"lightness":0.25}"#).unwrap();
+        assert_close(c, Color::rgb(0.5, 0., 0.), EPSILON);
+    }
+
+    #[test]
+    fn deserialize_malformed_hex() {
+        for json in [r##""#ff80""##, r##""#gg0000""##, r#""""#] {
+            assert!(from_json(json).is_err(), "{}", json);
+        }
+    }
+}
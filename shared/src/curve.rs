@@ -0,0 +1,134 @@
+use serde::{Deserialize, Serialize};
+
+use crate::Color;
+
+/// Per-channel multipliers, used to white balance a fixture.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct Gain {
+    pub red: f32,
+    pub green: f32,
+    pub blue: f32,
+    pub white: f32,
+    pub amber: f32,
+    pub uv: f32,
+}
+
+impl Default for Gain {
+    fn default() -> Self {
+        Self {
+            red: 1.,
+            green: 1.,
+            blue: 1.,
+            white: 1.,
+            amber: 1.,
+            uv: 1.,
+        }
+    }
+}
+
+/// Correction applied to colors before they are encoded for a subscriber.
+/// Stages are applied in order: gamma, then gain, then the lookup table.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct OutputCurve {
+    /// Exponent applied to each component; 1.0 is linear.
+    pub gamma: f32,
+    pub gain: Gain,
+    /// Optional transfer function sampled at evenly-spaced unit inputs.
+    /// Values between samples are linearly interpolated. Every sample must
+    /// be a unit float.
+    pub lut: Option<Vec<f32>>,
+}
+
+impl Default for OutputCurve {
+    fn default() -> Self {
+        Self {
+            gamma: 1.,
+            gain: Gain::default(),
+            lut: None,
+        }
+    }
+}
+
+impl OutputCurve {
+    /// Return true if this curve can be applied without producing garbage.
+    pub fn is_valid(&self) -> bool {
+        let Gain {
+            red,
+            green,
+            blue,
+            white,
+            amber,
+            uv,
+        } = self.gain;
+        self.gamma.is_finite()
+            && self.gamma > 0.
+            && [red, green, blue, white, amber, uv]
+                .iter()
+                .all(|g| g.is_finite() && *g >= 0.)
+            && self
+                .lut
+                .as_ref()
+                .map(|lut| lut.len() >= 2 && lut.iter().all(|v| (0. ..=1.).contains(v)))
+                .unwrap_or(true)
+    }
+
+    pub fn apply(&self, color: Color) -> Color {
+        Color {
+            red: self.apply_channel(color.red, self.gain.red),
+            green: self.apply_channel(color.green, self.gain.green),
+            blue: self.apply_channel(color.blue, self.gain.blue),
+            white: color.white.map(|v| self.apply_channel(v, self.gain.white)),
+            amber: color.amber.map(|v| self.apply_channel(v, self.gain.amber)),
+            uv: color.uv.map(|v| self.apply_channel(v, self.gain.uv)),
+        }
+    }
+
+    fn apply_channel(&self, v: f32, gain: f32) -> f32 {
+        let v = (v.clamp(0., 1.).powf(self.gamma) * gain).clamp(0., 1.);
+        match self.lut {
+            // Curves are validated, but never send an out of range level.
+            Some(ref lut) => lookup(lut, v).clamp(0., 1.),
+            None => v,
+        }
+    }
+}
+
+/// Linearly interpolate into a table spanning the unit range.
+fn lookup(lut: &[f32], v: f32) -> f32 {
+    if lut.len() < 2 {
+        return v;
+    }
+    let pos = v * (lut.len() - 1) as f32;
+    let lower = (pos.floor() as usize).min(lut.len() - 2);
+    let frac = pos - lower as f32;
+    lut[lower] + (lut[lower + 1] - lut[lower]) * frac
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_lut(lut: Vec<f32>) -> OutputCurve {
+        OutputCurve {
+            lut: Some(lut),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn lut_values_must_be_unit_floats() {
+        assert!(with_lut(vec![0., 0.5, 1.]).is_valid());
+        assert!(!with_lut(vec![0., 1.5]).is_valid());
+        assert!(!with_lut(vec![-0.1, 1.]).is_valid());
+        assert!(!with_lut(vec![0., f32::NAN]).is_valid());
+        assert!(!with_lut(vec![1.]).is_valid());
+    }
+
+    #[test]
+    fn lut_output_is_clamped() {
+        let curve = with_lut(vec![-1., 2.]);
+        let out = curve.apply(Color::rgb(0., 0.5, 1.));
+        assert_eq!((out.red, out.green, out.blue), (0., 0.5, 1.));
+    }
+}
@@ -0,0 +1,61 @@
+use derive_more::Display;
+use serde::{Deserialize, Serialize};
+
+/// Palette motion locked to the tempo clock.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct BeatEffect {
+    pub pattern: BeatPattern,
+    /// The length of one step in beats; 0.5 steps on every eighth note.
+    #[serde(default = "BeatEffect::default_beats")]
+    pub beats: f32,
+}
+
+impl BeatEffect {
+    fn default_beats() -> f32 {
+        1.
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum BeatPattern {
+    /// Rotate the palette one swatch further each step.
+    StepRotate,
+    /// Light one swatch at a time, moving on each step.
+    Chase,
+    /// Flash every swatch at the start of each step, stepping through the
+    /// palette colors.
+    Strobe,
+    /// Brightness jumps to full at the start of each step, then decays.
+    Pulse,
+}
+
+/// Motion that runs continuously on top of the palette.
+/// Periods are in seconds and depths are unit floats.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum Effect {
+    /// The whole palette dims and brightens together.
+    Breathe { period: f32, depth: f32 },
+    /// Every hue turns around the color wheel, once per period.
+    HueDrift { period: f32 },
+    /// Swatches flash toward white at random and fade back.
+    /// Density is the average number of flashes per swatch per second.
+    Sparkle { density: f32, decay: f32 },
+    /// A band of brightness travels across the palette by index.
+    /// Wavelength is in swatches.
+    Wave {
+        period: f32,
+        wavelength: f32,
+        depth: f32,
+    },
+}
+
+/// A unique ID assigned to each effect when it is added.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Display, Serialize, Deserialize)]
+pub struct EffectId(u64);
+
+impl EffectId {
+    pub const ZERO: EffectId = EffectId(0);
+    pub fn advance(&mut self) {
+        self.0 += 1;
+    }
+}
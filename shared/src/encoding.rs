@@ -0,0 +1,16 @@
+use serde::{Deserialize, Serialize};
+
+/// How a subscriber wants each channel value encoded.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum Encoding {
+    /// A unit float per channel.
+    #[default]
+    Float,
+    /// An integer in the range 0-255 per channel.
+    Int8,
+    /// An integer in the range 0-65535 per channel.
+    Int16,
+    /// A pair of integers in the range 0-255 per channel, coarse then fine,
+    /// for fixtures that take 16-bit values as two 8-bit channels.
+    CoarseFine,
+}
@@ -0,0 +1,86 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+/// Parameters for extracting a palette from an image.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Extraction {
+    /// The number of dominant colors to extract.
+    pub count: usize,
+    #[serde(default)]
+    pub sort: SortBy,
+}
+
+/// Orderings for extracted colors.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum SortBy {
+    /// Most common first.
+    #[default]
+    Population,
+    /// Around the color wheel, starting from red.
+    Hue,
+    /// Darkest first.
+    Lightness,
+    /// Most saturated first.
+    Saturation,
+}
+
+impl FromStr for SortBy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "population" => Ok(Self::Population),
+            "hue" => Ok(Self::Hue),
+            "lightness" => Ok(Self::Lightness),
+            "saturation" => Ok(Self::Saturation),
+            _ => Err(format!(
+                "unknown sort \"{}\"; expected population, hue, lightness or saturation",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for SortBy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Population => "population",
+            Self::Hue => "hue",
+            Self::Lightness => "lightness",
+            Self::Saturation => "saturation",
+        };
+        f.write_str(name)
+    }
+}
+
+/// An encoded image file to extract a palette from.
+///
+/// Sent over the websocket as a binary message: the extraction parameters as
+/// a single line of JSON, a newline, then the raw image file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImageUpload {
+    pub extraction: Extraction,
+    pub image: Vec<u8>,
+}
+
+impl ImageUpload {
+    pub fn to_bytes(&self) -> Result<Vec<u8>, serde_json::Error> {
+        // Compact JSON never contains a raw newline.
+        let mut bytes = serde_json::to_vec(&self.extraction)?;
+        bytes.push(b'\n');
+        bytes.extend_from_slice(&self.image);
+        Ok(bytes)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, serde_json::Error> {
+        let split = bytes
+            .iter()
+            .position(|b| *b == b'\n')
+            .unwrap_or(bytes.len());
+        let extraction = serde_json::from_slice(&bytes[..split])?;
+        let image = bytes.get(split + 1..).unwrap_or_default().to_vec();
+        Ok(Self { extraction, image })
+    }
+}
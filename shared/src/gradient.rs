@@ -0,0 +1,67 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{lerp, Color, ColorSpace};
+
+/// A color pinned to a position along a gradient.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct GradientStop {
+    /// Unit float position along the gradient.
+    pub position: f32,
+    pub color: Color,
+}
+
+/// A palette defined as a gradient between keyframe stops.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Gradient {
+    pub stops: Vec<GradientStop>,
+    /// The color space to blend between stops in.
+    #[serde(default)]
+    pub space: ColorSpace,
+    /// The number of colors to sample from the gradient.
+    pub count: usize,
+}
+
+impl Gradient {
+    /// Sample the gradient at a unit position.
+    /// Positions before the first stop or after the last take the color of
+    /// the nearest stop. Returns black if there are no stops.
+    pub fn sample(&self, position: f32) -> Color {
+        let mut stops = self.stops.clone();
+        stops.sort_by(|a, b| a.position.total_cmp(&b.position));
+        sample_sorted(&stops, position, self.space)
+    }
+
+    /// Sample count evenly-spaced colors, including both ends.
+    pub fn colors(&self) -> Vec<Color> {
+        let mut stops = self.stops.clone();
+        stops.sort_by(|a, b| a.position.total_cmp(&b.position));
+        let last = self.count.saturating_sub(1).max(1) as f32;
+        (0..self.count)
+            .map(|i| sample_sorted(&stops, i as f32 / last, self.space))
+            .collect()
+    }
+}
+
+fn sample_sorted(stops: &[GradientStop], position: f32, space: ColorSpace) -> Color {
+    let (first, last) = match (stops.first(), stops.last()) {
+        (Some(first), Some(last)) => (first, last),
+        _ => return Color::BLACK,
+    };
+    if position <= first.position {
+        return first.color;
+    }
+    if position >= last.position {
+        return last.color;
+    }
+    for pair in stops.windows(2) {
+        let (a, b) = (pair[0], pair[1]);
+        if position <= b.position {
+            let span = b.position - a.position;
+            if span <= 0. {
+                return b.color;
+            }
+            return lerp(a.color, b.color, (position - a.position) / span, space);
+        }
+    }
+    last.color
+}
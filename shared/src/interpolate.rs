@@ -0,0 +1,155 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{Color, Hsl, Hsv, Oklab, Oklch};
+
+/// The color space in which to blend between two colors.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+pub enum ColorSpace {
+    /// Straight blend of the sRGB components.
+    Rgb,
+    Hsv,
+    Hsl,
+    /// Perceptually uniform; a good default for fades.
+    #[default]
+    Oklab,
+    /// Perceptually uniform, blending around the hue wheel.
+    Oklch,
+}
+
+/// Interpolate between two colors in the provided color space.
+/// t is a unit float; 0 produces a, 1 produces b.
+/// Hues take the shortest path around the wheel; if one color has no hue
+/// (a gray) the hue of the other is used throughout.
+/// White, amber and UV are blended linearly if either color carries them.
+pub fn lerp(a: Color, b: Color, t: f32, space: ColorSpace) -> Color {
+    let rgb: Color = match space {
+        ColorSpace::Rgb => Color::rgb(
+            lerp_f32(a.red, b.red, t),
+            lerp_f32(a.green, b.green, t),
+            lerp_f32(a.blue, b.blue, t),
+        ),
+        ColorSpace::Hsv => {
+            let (a, b) = (Hsv::from(a), Hsv::from(b));
+            Hsv {
+                hue: lerp_hue(a.hue, a.saturation, b.hue, b.saturation, t),
+                saturation: lerp_f32(a.saturation, b.saturation, t),
+                value: lerp_f32(a.value, b.value, t),
+            }
+            .into()
+        }
+        ColorSpace::Hsl => {
+            let (a, b) = (Hsl::from(a), Hsl::from(b));
+            Hsl {
+                hue: lerp_hue(a.hue, a.saturation, b.hue, b.saturation, t),
+                saturation: lerp_f32(a.saturation, b.saturation, t),
+                lightness: lerp_f32(a.lightness, b.lightness, t),
+            }
+            .into()
+        }
+        ColorSpace::Oklab => {
+            let (a, b) = (Oklab::from(a), Oklab::from(b));
+            Oklab {
+                lightness: lerp_f32(a.lightness, b.lightness, t),
+                a: lerp_f32(a.a, b.a, t),
+                b: lerp_f32(a.b, b.b, t),
+            }
+            .into()
+        }
+        ColorSpace::Oklch => {
+            let (a, b) = (Oklch::from(a), Oklch::from(b));
+            Oklch {
+                lightness: lerp_f32(a.lightness, b.lightness, t),
+                chroma: lerp_f32(a.chroma, b.chroma, t),
+                hue: lerp_hue(a.hue, a.chroma, b.hue, b.chroma, t),
+            }
+            .into()
+        }
+    };
+    Color {
+        white: lerp_option(a.white, b.white, t),
+        amber: lerp_option(a.amber, b.amber, t),
+        uv: lerp_option(a.uv, b.uv, t),
+        ..rgb
+    }
+}
+
+/// Blend optional components, treating an absent component as zero.
+fn lerp_option(a: Option<f32>, b: Option<f32>, t: f32) -> Option<f32> {
+    if a.is_none() && b.is_none() {
+        return None;
+    }
+    Some(lerp_f32(a.unwrap_or(0.), b.unwrap_or(0.), t))
+}
+
+fn lerp_f32(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+/// Below this saturation or chroma, a color's hue is considered meaningless.
+const ACHROMATIC: f32 = 1e-4;
+
+/// Interpolate between two unit hues along the shortest path.
+/// Each hue is accompanied by its saturation or chroma, so achromatic
+/// endpoints can borrow the hue of the other endpoint.
+pub fn lerp_hue(a: f32, a_sat: f32, b: f32, b_sat: f32, t: f32) -> f32 {
+    let (a, b) = match (a_sat < ACHROMATIC, b_sat < ACHROMATIC) {
+        (true, false) => (b, b),
+        (false, true) => (a, a),
+        _ => (a, b),
+    };
+    let delta = (b - a + 0.5).rem_euclid(1.) - 0.5;
+    (a + delta * t).rem_euclid(1.)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Distance between two unit hues around the wheel.
+    fn hue_distance(a: f32, b: f32) -> f32 {
+        let d = (a - b).rem_euclid(1.);
+        d.min(1. - d)
+    }
+
+    #[test]
+    fn hue_takes_short_way_across_wrap() {
+        assert!(hue_distance(lerp_hue(0.95, 1., 0.05, 1., 0.5), 0.) < 1e-5);
+        assert!(hue_distance(lerp_hue(0.95, 1., 0.05, 1., 0.25), 0.975) < 1e-5);
+        assert!(hue_distance(lerp_hue(0.05, 1., 0.95, 1., 0.25), 0.025) < 1e-5);
+        // Without a wrap, the short way is the direct one.
+        assert!(hue_distance(lerp_hue(0.2, 1., 0.4, 1., 0.5), 0.3) < 1e-5);
+    }
+
+    #[test]
+    fn hue_stays_in_unit_range() {
+        for t in [0., 0.1, 0.5, 0.9, 1.] {
+            let hue = lerp_hue(0.9, 1., 0.2, 1., t);
+            assert!((0. ..1.).contains(&hue), "{}", hue);
+        }
+    }
+
+    #[test]
+    fn achromatic_endpoint_borrows_hue() {
+        assert_eq!(lerp_hue(0.7, 0., 0.3, 1., 0.5), 0.3);
+        assert_eq!(lerp_hue(0.3, 1., 0.7, 0., 0.5), 0.3);
+    }
+
+    #[test]
+    fn lerp_oklch_across_wrap() {
+        // Magenta-ish red and orange-ish red sit either side of hue zero
+        // in OKLCh, so their midpoint should stay red rather than pass
+        // through green.
+        let a = Color::from(Oklch {
+            lightness: 0.6,
+            chroma: 0.15,
+            hue: 0.95,
+        });
+        let b = Color::from(Oklch {
+            lightness: 0.6,
+            chroma: 0.15,
+            hue: 0.05,
+        });
+        let mid = Oklch::from(lerp(a, b, 0.5, ColorSpace::Oklch));
+        assert!(hue_distance(mid.hue, 0.) < 0.01, "{:?}", mid);
+    }
+}
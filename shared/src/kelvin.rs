@@ -0,0 +1,73 @@
+// Coefficients are kept exactly as published.
+#![allow(clippy::excessive_precision)]
+
+use serde::{Deserialize, Serialize};
+
+use crate::color::linear_to_srgb;
+use crate::Color;
+
+/// A white specified by correlated color temperature.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct Cct {
+    /// Color temperature in Kelvin, clamped to 1667-25000.
+    pub kelvin: f32,
+    /// Distance from the blackbody locus in CIE 1960 uv (Duv).
+    /// Positive values shift toward green, negative toward magenta; useful
+    /// values are within about 0.02 of zero.
+    #[serde(default)]
+    pub tint: f32,
+}
+
+impl Cct {
+    pub const MIN_KELVIN: f32 = 1667.;
+    pub const MAX_KELVIN: f32 = 25000.;
+}
+
+impl From<Cct> for Color {
+    /// Produce the brightest color with this chromaticity.
+    fn from(cct: Cct) -> Self {
+        let kelvin = cct.kelvin.clamp(Cct::MIN_KELVIN, Cct::MAX_KELVIN);
+        let (u, v) = planckian_uv(kelvin);
+        // Offset along the normal to the locus, pointing toward green.
+        let (u2, v2) = planckian_uv(kelvin * 1.01);
+        let (du, dv) = (u2 - u, v2 - v);
+        let len = du.hypot(dv);
+        let (u, v) = (u + cct.tint * dv / len, v - cct.tint * du / len);
+
+        // CIE 1960 uv to xy, then to XYZ with unit luminance.
+        let denom = 2. * u - 8. * v + 4.;
+        let (x, y) = (3. * u / denom, 2. * v / denom);
+        let (big_x, big_y, big_z) = (x / y, 1., (1. - x - y) / y);
+
+        let r = 3.2404542 * big_x - 1.5371385 * big_y - 0.4985314 * big_z;
+        let g = -0.9692660 * big_x + 1.8760108 * big_y + 0.0415560 * big_z;
+        let b = 0.0556434 * big_x - 0.2040259 * big_y + 1.0572252 * big_z;
+        let (r, g, b) = (r.max(0.), g.max(0.), b.max(0.));
+        let max = r.max(g).max(b);
+        Color::rgb(
+            linear_to_srgb(r / max),
+            linear_to_srgb(g / max),
+            linear_to_srgb(b / max),
+        )
+    }
+}
+
+/// Chromaticity of a blackbody radiator in CIE 1960 uv, using the cubic
+/// spline approximation of Kim et al.
+fn planckian_uv(kelvin: f32) -> (f32, f32) {
+    let t = kelvin;
+    let x = if t <= 4000. {
+        -0.2661239e9 / t.powi(3) - 0.2343589e6 / t.powi(2) + 0.8776956e3 / t + 0.179910
+    } else {
+        -3.0258469e9 / t.powi(3) + 2.1070379e6 / t.powi(2) + 0.2226347e3 / t + 0.240390
+    };
+    let y = if t <= 2222. {
+        -1.1063814 * x.powi(3) - 1.34811020 * x.powi(2) + 2.18555832 * x - 0.20219683
+    } else if t <= 4000. {
+        -0.9549476 * x.powi(3) - 1.37418593 * x.powi(2) + 2.09137015 * x - 0.16748867
+    } else {
+        3.0817580 * x.powi(3) - 5.87338670 * x.powi(2) + 3.75112997 * x - 0.37001483
+    };
+    let denom = -2. * x + 12. * y + 3.;
+    (4. * x / denom, 6. * y / denom)
+}
@@ -1,65 +1,315 @@
 use std::net::SocketAddr;
+use std::ops::RangeInclusive;
 
 use derive_more::Display;
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
-pub struct Color {
-    pub red: f32,
-    pub green: f32,
-    pub blue: f32,
-}
-
-impl Color {
-    pub fn as_u8(&self) -> (u8, u8, u8) {
-        (
-            unipolar_float_to_u8(self.red),
-            unipolar_float_to_u8(self.green),
-            unipolar_float_to_u8(self.blue),
-        )
-    }
-}
+mod channels;
+mod color;
+mod curve;
+mod effect;
+mod encoding;
+mod extract;
+mod gradient;
+mod interpolate;
+mod kelvin;
+mod palette_file;
+mod schedule;
 
-/// Convert a unit float to an 8-bit integer.
-/// Uses rounding instead of floor to ensure we divide up the unit range into
-/// bins of equal size.
-fn unipolar_float_to_u8(f: f32) -> u8 {
-    (f * 255.).round() as u8
-}
+pub use channels::{Channels, WhiteDerivation};
+pub use color::{
+    unipolar_float_to_u16, unipolar_float_to_u8, Color, Hsl, Hsv, InvalidColor, Oklab, Oklch,
+    ParseColorError,
+};
+pub use curve::{Gain, OutputCurve};
+pub use effect::{BeatEffect, BeatPattern, Effect, EffectId};
+pub use encoding::Encoding;
+pub use extract::{Extraction, ImageUpload, SortBy};
+pub use gradient::{Gradient, GradientStop};
+pub use interpolate::{lerp, lerp_hue, ColorSpace};
+pub use kelvin::Cct;
+pub use palette_file::{PaletteFile, PaletteFormat};
+pub use schedule::{ScheduleId, Scheduled, Trigger};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum ControlMessage {
     Refresh,
     Palette(PaletteControlMessage),
     Subscriber(SubscriberControlMessage),
+    Master(MasterControlMessage),
+    Cue(CueControlMessage),
+    Clock(ClockControlMessage),
+    Effect(EffectControlMessage),
+    Schedule(ScheduleControlMessage),
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum StateChange {
     Palette(PaletteStateChange),
     Subscriber(SubscriberStateChange),
+    Master(MasterStateChange),
+    Cue(CueStateChange),
+    Clock(ClockStateChange),
+    Effect(EffectStateChange),
+    Schedule(ScheduleStateChange),
+    /// The number of connected websocket clients changed.
+    Clients(ClientCounts),
+    /// A control message was rejected.
+    Error(String),
+}
+
+/// How many websocket clients are connected, by whether they may make
+/// changes.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ClientCounts {
+    pub editors: usize,
+    /// Clients that only receive state, such as preview monitors.
+    pub observers: usize,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum PaletteControlMessage {
     Set(Vec<Color>),
+    /// Set the palette from a mix of colors and color temperatures.
+    SetEntries(Vec<PaletteEntry>),
+    /// Replace the palette with colors generated from a harmony rule.
+    Generate(Harmony),
+    /// Set the palette by sampling a gradient.
+    SetGradient(Gradient),
+    /// Set the palette to the dominant colors of an image.
+    Extract(ImageUpload),
+    /// Set the palette from the contents of a palette file.
+    Import(PaletteFile),
+    /// Ask for the current palette encoded in a file format.
+    Export(PaletteFormat),
+    /// Replace the palette with random colors drawn within constraints.
+    Randomize(Randomization),
+    /// Lock or unlock the swatch at an index. Locked swatches keep their
+    /// color when the palette is replaced.
+    LockSwatch(usize, bool),
+    /// Lock or unlock the whole palette. A locked palette rejects edits.
+    Lock(bool),
+    /// Save the current colors under a name, replacing any palette already
+    /// saved with that name.
+    Save(String),
+    /// Set the palette to the colors saved under a name.
+    Recall(String),
+    /// Forget a saved palette.
+    Delete(String),
+}
+
+/// The most colors a palette or cue may have.
+pub const MAX_PALETTE_SIZE: usize = 1024;
+
+/// Parameters for generating a palette from a base color.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Harmony {
+    pub base: Color,
+    pub rule: HarmonyRule,
+    /// The number of colors to generate, at most MAX_PALETTE_SIZE.
+    pub count: usize,
+}
+
+/// Classic color wheel relationships.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum HarmonyRule {
+    Complementary,
+    Triadic,
+    Analogous,
+    SplitComplementary,
+    Tetradic,
+    /// Shades and tints of the base hue.
+    Monochromatic,
+}
+
+/// Constraints for generating a random palette.
+/// Hues are unconstrained; saturation and lightness are HSL unit floats.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Randomization {
+    /// The number of colors to generate.
+    pub count: usize,
+    #[serde(default = "Randomization::default_saturation")]
+    pub saturation: RangeInclusive<f32>,
+    #[serde(default = "Randomization::default_lightness")]
+    pub lightness: RangeInclusive<f32>,
+    /// The minimum Oklab distance between any two colors.
+    #[serde(default = "Randomization::default_min_distance")]
+    pub min_distance: f32,
+    /// Seed the generator to reproduce a palette.
+    #[serde(default)]
+    pub seed: Option<u64>,
+}
+
+impl Randomization {
+    fn default_saturation() -> RangeInclusive<f32> {
+        0.5..=1.
+    }
+
+    fn default_lightness() -> RangeInclusive<f32> {
+        0.35..=0.65
+    }
+
+    fn default_min_distance() -> f32 {
+        0.1
+    }
+}
+
+/// A palette swatch, specified in any of the ways operators think of color.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum PaletteEntry {
+    Color(Color),
+    Cct(Cct),
+}
+
+impl From<PaletteEntry> for Color {
+    fn from(entry: PaletteEntry) -> Self {
+        match entry {
+            PaletteEntry::Color(c) => c,
+            PaletteEntry::Cct(c) => c.into(),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum PaletteStateChange {
     Set(Vec<Color>),
+    /// The palette was sampled from this gradient, producing these colors.
+    SetGradient(Gradient, Vec<Color>),
+    /// The current palette, encoded as requested.
+    Exported(PaletteFile),
+    /// Lock flags for each swatch, by index.
+    SwatchLocks(Vec<bool>),
+    Locked(bool),
+    Saved(String, Vec<Color>),
+    Deleted(String),
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum SubscriberControlMessage {
     Add(SubscriberConfig),
     Remove(SubscriberId),
+    SetCurve(SubscriberId, OutputCurve),
+    SetChannels(SubscriberId, Channels),
+    SetEncoding(SubscriberId, Encoding),
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum SubscriberStateChange {
     Added(Subscriber),
     Removed(SubscriberId),
+    CurveSet(SubscriberId, OutputCurve),
+    ChannelsSet(SubscriberId, Channels),
+    EncodingSet(SubscriberId, Encoding),
+}
+
+/// Global overrides applied to everything sent to subscribers.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum MasterControlMessage {
+    /// Set the master intensity, a unit float scaling all outgoing colors.
+    Level(f32),
+    /// While engaged, send black to every subscriber.
+    Blackout(bool),
+    /// While engaged, hold the current output and ignore palette edits.
+    Freeze(bool),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum MasterStateChange {
+    Level(f32),
+    Blackout(bool),
+    Freeze(bool),
+}
+
+/// A look in a programmed show.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Cue {
+    #[serde(default)]
+    pub name: String,
+    pub colors: Vec<Color>,
+    /// Seconds to crossfade from the previous look.
+    #[serde(default)]
+    pub fade: f32,
+    /// Seconds to hold once the fade completes, before following on.
+    #[serde(default)]
+    pub hold: f32,
+    /// If true, go to the next cue once the fade and hold have elapsed.
+    #[serde(default)]
+    pub follow: bool,
+}
+
+/// Control the cue list. The list loops: Go from the last cue runs the
+/// first, and Back from the first cue runs the last.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum CueControlMessage {
+    /// Replace the cue list. Nothing is running afterwards.
+    SetCues(Vec<Cue>),
+    Go,
+    Back,
+    /// Run the cue at this index.
+    Jump(usize),
+    /// While paused, fades and hold timers stop where they are.
+    Pause(bool),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum CueStateChange {
+    CuesSet(Vec<Cue>),
+    /// The index of the running cue, if any.
+    Current(Option<usize>),
+    Paused(bool),
+}
+
+/// Control the tempo clock that beat-synced effects follow.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ClockControlMessage {
+    /// Tap along with the beat. Each tap lands on a downbeat; two or more
+    /// taps in quick succession set the tempo.
+    Tap,
+    SetBpm(f32),
+    /// A beat from an external source landed now. The clock pulls its
+    /// phase toward the beat and follows the tempo of successive beats.
+    Beat,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ClockStateChange {
+    Bpm(f32),
+}
+
+/// Effects are applied in the order they were added, then the beat effect.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum EffectControlMessage {
+    /// Run a beat-synced effect, or stop it with None.
+    SetBeat(Option<BeatEffect>),
+    Add(Effect),
+    Remove(EffectId),
+    /// Change the parameters of a running effect.
+    Update(EffectId, Effect),
+    /// Remove every continuous effect.
+    Clear,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum EffectStateChange {
+    Beat(Option<BeatEffect>),
+    Added(EffectId, Effect),
+    Removed(EffectId),
+    Updated(EffectId, Effect),
+    Cleared,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ScheduleControlMessage {
+    Add(Scheduled),
+    Remove(ScheduleId),
+    Update(ScheduleId, Scheduled),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ScheduleStateChange {
+    Added(ScheduleId, Scheduled),
+    Removed(ScheduleId),
+    Updated(ScheduleId, Scheduled),
 }
 
 /// A unique ID assigned to each subscriber when it is added.
@@ -78,6 +328,14 @@ impl SubscriberId {
 pub struct Subscriber {
     pub id: SubscriberId,
     pub cfg: SubscriberConfig,
+    /// Correction applied to colors before they are sent to this subscriber.
+    #[serde(default)]
+    pub curve: OutputCurve,
+    /// Emitters beyond red, green and blue that this subscriber wants.
+    #[serde(default)]
+    pub channels: Channels,
+    #[serde(default)]
+    pub encoding: Encoding,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
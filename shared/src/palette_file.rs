@@ -0,0 +1,66 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+/// File formats palettes can be imported from and exported to.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum PaletteFormat {
+    /// GIMP palette, .gpl.
+    Gpl,
+    /// Adobe Swatch Exchange, .ase.
+    Ase,
+    /// CSS custom properties.
+    Css,
+    /// Hex colors, one per line.
+    Hex,
+    /// A JSON array of colors.
+    Json,
+}
+
+impl PaletteFormat {
+    pub const ALL: [PaletteFormat; 5] = [Self::Gpl, Self::Ase, Self::Css, Self::Hex, Self::Json];
+
+    /// The conventional file extension for this format.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Gpl => "gpl",
+            Self::Ase => "ase",
+            Self::Css => "css",
+            Self::Hex => "hex",
+            Self::Json => "json",
+        }
+    }
+
+    pub fn from_extension(ext: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|f| f.extension().eq_ignore_ascii_case(ext))
+    }
+}
+
+impl FromStr for PaletteFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::from_extension(s).ok_or_else(|| {
+            format!(
+                "unknown palette format \"{}\"; expected gpl, ase, css, hex or json",
+                s
+            )
+        })
+    }
+}
+
+impl fmt::Display for PaletteFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.extension())
+    }
+}
+
+/// The contents of a palette file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PaletteFile {
+    pub format: PaletteFormat,
+    pub data: Vec<u8>,
+}
@@ -0,0 +1,46 @@
+use derive_more::Display;
+use serde::{Deserialize, Serialize};
+
+use crate::ControlMessage;
+
+/// A control message sent automatically at wall-clock times.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Scheduled {
+    #[serde(default)]
+    pub name: String,
+    pub trigger: Trigger,
+    pub action: Box<ControlMessage>,
+    /// Disabled actions keep their place in the schedule but never fire.
+    #[serde(default = "Scheduled::default_enabled")]
+    pub enabled: bool,
+}
+
+impl Scheduled {
+    fn default_enabled() -> bool {
+        true
+    }
+}
+
+/// When a scheduled action fires, in the server's local time.
+/// Actions that come due while the server is not running are skipped.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub enum Trigger {
+    /// Once, at a date and time such as "2024-06-01 20:30:00".
+    At(String),
+    /// Whenever a cron expression matches. Expressions have a leading
+    /// seconds field: "0 30 20 * * Mon-Fri" is 8:30 PM on weekdays.
+    Cron(String),
+}
+
+/// A unique ID assigned to each scheduled action when it is added.
+#[derive(
+    Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Display, Serialize, Deserialize,
+)]
+pub struct ScheduleId(u64);
+
+impl ScheduleId {
+    pub const ZERO: ScheduleId = ScheduleId(0);
+    pub fn advance(&mut self) {
+        self.0 += 1;
+    }
+}